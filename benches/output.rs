@@ -0,0 +1,52 @@
+// Compare the output of a large listing written through a buffer and written line by line,
+// the latter is what the stdout does by default. Run it by 'cargo bench'.
+use std::{
+    fs::File,
+    io::{self, BufWriter, LineWriter, Write},
+    time::{Duration, Instant},
+};
+
+use new_command::ls::{list_dir, ListOptions, Renderer};
+
+const FILES: usize = 20_000;
+const ROUNDS: u32 = 10;
+
+fn main() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    for i in 0..FILES {
+        File::create(dir.path().join(format!("file{:05}", i)))?;
+    }
+
+    let opts = ListOptions {
+        long: true,
+        ..ListOptions::default()
+    };
+    let files = list_dir(dir.path(), &opts)?;
+    let renderer = Renderer::new(&opts);
+
+    // The output goes to a real file, so every flush is a real syscall.
+    let unbuffered = measure(|| {
+        let mut out = LineWriter::new(tempfile::tempfile()?);
+        renderer.show_infos(&files, &mut out)?;
+        out.flush()
+    })?;
+    let buffered = measure(|| {
+        let mut out = BufWriter::new(tempfile::tempfile()?);
+        renderer.show_infos(&files, &mut out)?;
+        out.flush()
+    })?;
+
+    println!("{} files, the average of {} rounds:", FILES, ROUNDS);
+    println!("    line by line: {:?}", unbuffered);
+    println!("    buffered:     {:?}", buffered);
+    Ok(())
+}
+
+// Run the output for the rounds and get the average time of them.
+fn measure(mut output: impl FnMut() -> io::Result<()>) -> io::Result<Duration> {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        output()?;
+    }
+    Ok(start.elapsed() / ROUNDS)
+}
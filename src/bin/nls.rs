@@ -1,88 +1,519 @@
-use libc::getgrgid;
-use new_command::Cli;
+use new_command::{
+    ls::{
+        build_tree, columns_from_env, describe_error, get_file_info, iter_dir, list_dir,
+        sort_files, terminal_width, BlockSize, Config, Depth, FileFilter, FileInfo, FullPath,
+        ListOptions, LsColors, QuotingStyle, Renderer, SortBy, TimeField, TimeStyle,
+    },
+    Cli,
+};
 use std::{
+    borrow::Borrow,
+    env,
+    ffi::OsString,
     fmt::Debug,
-    fs,
-    os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt},
+    io::{self, BufWriter, IsTerminal, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Instant,
 };
 
-use std::ffi::CStr;
-
-use chrono::{DateTime, Local};
-use clap::Parser;
-use colored::*;
-use users::{get_group_by_gid, get_user_by_uid};
-
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum FileType {
-    File,
-    Dir,
-    Link,
-    CharDevice,
-    BlockDevice,
-    Fifo,
-    Socket,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct FileInfo {
-    file_type: FileType,
-    permissions: String,
-    link: u64,
-    owner: String,
-    group: String,
-    size: u64,
-    modified_time: String,
-    name: String,
-    is_hidden: bool,
+use clap::{ArgAction, CommandFactory, Parser, ValueEnum};
+use clap_complete::Shell;
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+use serde_json::json;
+
+// When to turn a feature on, just like the '--color' and '--hyperlink' options of GNU ls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum When {
+    Auto,
+    Always,
+    Never,
 }
 
 #[derive(Debug, Parser)]
 #[command(
     author = "Tianyi",
     version = "0.0.1",
-    about = "A new command line tool written in Rust"
+    about = "A new command line tool written in Rust",
+    // A flag given again replaces the earlier one, so the command line beats NLS_OPTIONS.
+    args_override_self = true,
+    // '-h' is taken by the human readable sizes just like GNU ls, so the help only has '--help'.
+    disable_help_flag = true
 )]
 struct LsCli {
     #[arg(short = 'l', help = "show details of files and directories")]
     long: bool,
 
-    #[arg(short = 'a', long = "all", help = "show hidden files and directories")]
+    #[arg(
+        short = 'a',
+        long = "all",
+        overrides_with = "almost_all",
+        help = "show hidden files and directories, '.' and '..' included"
+    )]
     all: bool,
 
     #[arg(
-        short = 'H',
+        short = 'A',
+        long = "almost-all",
+        overrides_with = "all",
+        help = "show hidden files and directories, except '.' and '..'"
+    )]
+    almost_all: bool,
+
+    #[arg(
+        short = 'h',
         long = "human-readable",
         help = "show human readable file sizes"
     )]
     human_readable: bool,
 
-    #[arg(default_value = ".", help = "set file or directory path")]
-    path: Option<std::path::PathBuf>,
+    #[arg(
+        long = "allocated-size",
+        overrides_with = "apparent_size",
+        help = "show the sizes allocated on the disk instead of the apparent sizes"
+    )]
+    allocated_size: bool,
+
+    #[arg(
+        long = "apparent-size",
+        overrides_with = "allocated_size",
+        help = "show the apparent sizes of files, it is the default"
+    )]
+    apparent_size: bool,
+
+    #[arg(
+        long = "total-size",
+        help = "show the total sizes of the content of directories, like 'du'"
+    )]
+    total_size: bool,
+
+    #[arg(long = "si", help = "like -h, but use powers of 1000 not 1024")]
+    si: bool,
+
+    #[arg(
+        long = "block-size",
+        value_name = "SIZE",
+        help = "scale sizes by SIZE before showing them, e.g. '1K', '1MB', '512' or 'human-readable'"
+    )]
+    block_size: Option<BlockSize>,
+
+    #[arg(
+        long = "bytes",
+        conflicts_with_all = ["human_readable", "si"],
+        help = "show the sizes in bytes with thousands separators, e.g. '1,048,576'"
+    )]
+    bytes: bool,
+
+    #[arg(short = 'k', long = "kibibytes", help = "like --block-size=1K")]
+    kibibytes: bool,
 
-    #[arg(short = 's', long = "size", help = "sort by file size")]
+    #[arg(
+        short = 'I',
+        long = "ignore",
+        value_name = "PATTERN",
+        value_parser = Pattern::new,
+        help = "do not list entries matching the shell PATTERN, it can be given many times"
+    )]
+    ignore: Vec<Pattern>,
+
+    #[arg(
+        long = "hide",
+        value_name = "PATTERN",
+        value_parser = Pattern::new,
+        help = "do not list entries matching the shell PATTERN, unless '-a' or '-A' is given"
+    )]
+    hide: Vec<Pattern>,
+
+    #[arg(
+        long = "filter",
+        value_enum,
+        value_name = "TYPE",
+        value_delimiter = ',',
+        help = "only list the entries of the TYPEs: file, dir, link or exec, e.g. '--filter=dir,link'"
+    )]
+    filter: Vec<FileFilter>,
+
+    #[arg(
+        long = "grep",
+        value_name = "PATTERN",
+        value_parser = Regex::new,
+        help = "only list the entries whose names match the regular expression PATTERN"
+    )]
+    grep: Option<Regex>,
+
+    #[arg(
+        long = "grep-invert",
+        requires = "grep",
+        help = "only list the entries whose names don't match the PATTERN of '--grep'"
+    )]
+    grep_invert: bool,
+
+    #[arg(
+        long = "time-style",
+        value_name = "STYLE",
+        default_value = "long-iso",
+        help = "show times in the STYLE: iso, long-iso, full-iso or +FORMAT of strftime"
+    )]
+    time_style: TimeStyle,
+
+    #[arg(long = "full-time", help = "like -l --time-style=full-iso")]
+    full_time: bool,
+
+    #[arg(
+        long = "relative-time",
+        help = "show the modified time as how long ago it was, e.g. '2 hours ago'"
+    )]
+    relative_time: bool,
+
+    #[arg(
+        long = "octal-permissions",
+        help = "show the octal mode before the permissions in the details, e.g. '0755'"
+    )]
+    octal_permissions: bool,
+
+    #[arg(
+        short = 'n',
+        long = "numeric-uid-gid",
+        help = "like -l, but list numeric user and group IDs"
+    )]
+    numeric_uid_gid: bool,
+
+    #[arg(long = "author", help = "like -l, and list the author of each file")]
+    author: bool,
+
+    #[arg(short = 'o', help = "like -l, but do not list group information")]
+    no_group: bool,
+
+    #[arg(short = 'g', help = "like -l, but do not list owner")]
+    no_owner: bool,
+
+    #[arg(
+        value_name = "PATH",
+        default_value = ".",
+        help = "set the files or directories to list"
+    )]
+    paths: Vec<PathBuf>,
+
+    #[arg(
+        short = 'S',
+        short_alias = 's',
+        long = "size",
+        help = "sort by file size, largest first"
+    )]
     sort_by_size: bool,
 
-    #[arg(short = 't', long = "time", help = "sort by modified time")]
+    #[arg(
+        short = 't',
+        help = "sort by time, newest first, the modified time unless '--time', '-u' or '-c' chooses another"
+    )]
     sort_by_time: bool,
 
+    #[arg(
+        long = "time",
+        value_enum,
+        value_name = "WORD",
+        help = "show and sort on the time WORD instead of the modified time, it wins over '-u' and '-c'"
+    )]
+    time: Option<TimeField>,
+
+    #[arg(
+        short = 'u',
+        overrides_with = "change_time",
+        help = "show and sort on the access time"
+    )]
+    access_time: bool,
+
+    #[arg(
+        short = 'c',
+        overrides_with = "access_time",
+        help = "show and sort on the change time of the metadata"
+    )]
+    change_time: bool,
+
+    #[arg(short = 'X', help = "sort alphabetically by entry extension")]
+    sort_by_extension: bool,
+
+    // There is no long name for it, because '--version' is taken by the version of the command,
+    // use '--sort=version' instead.
+    #[arg(short = 'v', help = "natural sort of (version) numbers within names")]
+    sort_by_version: bool,
+
+    #[arg(
+        short = 'U',
+        help = "do not sort, list the entries in the order of the directory"
+    )]
+    unsorted: bool,
+
+    #[arg(
+        long = "sort",
+        value_enum,
+        value_name = "WORD",
+        help = "sort by WORD instead of name, it wins over the other sort options"
+    )]
+    sort: Option<SortBy>,
+
+    #[arg(
+        long = "ignore-case",
+        help = "sort the names regardless of case, e.g. 'apple' before 'Zebra'"
+    )]
+    ignore_case: bool,
+
     #[arg(short = 'r', long = "reverse", help = "reverse sort")]
     resort: bool,
 
+    #[arg(
+        long = "group-directories-first",
+        help = "group directories before files"
+    )]
+    group_directories_first: bool,
+
+    #[arg(
+        short = 'd',
+        long = "directory",
+        help = "list directories themselves, not their contents"
+    )]
+    directory: bool,
+
+    #[arg(
+        short = 'L',
+        long = "dereference",
+        help = "show information for the file the symbolic link references rather than the link itself"
+    )]
+    dereference: bool,
+
+    #[arg(
+        short = 'H',
+        long = "dereference-command-line",
+        help = "follow the symbolic links given on the command line, but not the ones in directories"
+    )]
+    dereference_command_line: bool,
+
+    #[arg(
+        short = 'i',
+        long = "inode",
+        help = "print the index number of each file"
+    )]
+    inode: bool,
+
+    #[arg(
+        short = 'F',
+        long = "classify",
+        help = "append indicator (one of */=@|) to entries"
+    )]
+    classify: bool,
+
+    #[arg(short = 'p', help = "append / indicator to directories")]
+    slash_dirs: bool,
+
+    #[arg(
+        short = '1',
+        overrides_with_all = ["commas", "across", "columns"],
+        help = "list one file per line"
+    )]
+    one_per_line: bool,
+
+    #[arg(
+        short = 'm',
+        overrides_with_all = ["one_per_line", "across", "columns"],
+        help = "list the names separated by commas, wrapped at the width of the terminal"
+    )]
+    commas: bool,
+
+    #[arg(
+        short = 'x',
+        overrides_with_all = ["one_per_line", "commas", "columns"],
+        help = "list the names by lines instead of by columns"
+    )]
+    across: bool,
+
+    #[arg(
+        short = 'C',
+        overrides_with_all = ["one_per_line", "commas", "across"],
+        help = "list the names by columns, even when the output is not a terminal"
+    )]
+    columns: bool,
+
+    #[arg(
+        short = 'w',
+        long = "width",
+        value_name = "COLS",
+        help = "lay the names out for a terminal COLS wide, 0 means no limit"
+    )]
+    width: Option<usize>,
+
+    #[arg(
+        short = 'R',
+        long = "recursive",
+        help = "list subdirectories recursively"
+    )]
+    recursive: bool,
+
     #[arg(
         short = 'T',
         long = "tree",
-        help = "show files and directories as a tree"
+        conflicts_with = "recursive",
+        help = "show files and directories as a tree, the branches are sorted like the listing, so '-t', '-S' and '-r' work on them, and '-l' shows the sizes"
     )]
     tree: bool,
 
     #[arg(
-        short = 'd',
         long = "depth",
-        help = "set the depth of the tree, default is 10",
-        default_value = "10"
+        help = "set how many levels below the directory the tree and the recursive listing go, 0 shows just its entries, default is 'inf' for no limit"
+    )]
+    depth: Option<Depth>,
+
+    #[arg(
+        long = "max-entries",
+        value_name = "N",
+        help = "show at most N children of each directory in the tree, the rest are counted in '... (K more)'"
+    )]
+    max_entries: Option<usize>,
+
+    #[arg(
+        long = "dirs-only",
+        help = "show only the directories in the tree, like 'tree -d'"
+    )]
+    dirs_only: bool,
+
+    #[arg(long = "mounts", help = "mark the mount points with '[mount point]'")]
+    mounts: bool,
+
+    #[arg(
+        long = "one-file-system",
+        help = "don't descend into the mount points in the tree and the recursive listing"
+    )]
+    one_file_system: bool,
+
+    #[arg(
+        long = "ascii",
+        help = "draw the branches of the tree with ASCII chars instead of Unicode"
+    )]
+    ascii: bool,
+
+    #[arg(
+        long = "threads",
+        alias = "jobs",
+        value_name = "N",
+        help = "read the metadata of large directories with N threads, default is the number of CPUs"
+    )]
+    threads: Option<usize>,
+
+    #[arg(
+        long = "json",
+        conflicts_with = "recursive",
+        help = "print files as JSON, '-l' and '-h' are ignored because all fields are included, use '-T' for the subdirectories, several paths make one object keyed by them"
+    )]
+    json: bool,
+
+    #[arg(
+        long = "color",
+        value_enum,
+        default_value = "auto",
+        help = "colorize the output, 'auto' only colors when writing to a terminal"
+    )]
+    color: When,
+
+    #[arg(
+        long = "icons",
+        value_enum,
+        default_value = "never",
+        help = "show the icons of Nerd Fonts before the names, 'auto' only shows them on a terminal"
+    )]
+    icons: When,
+
+    #[arg(
+        long = "hyperlink",
+        value_enum,
+        default_value = "never",
+        help = "link the names to the files for the terminals, 'auto' only links on a terminal"
+    )]
+    hyperlink: When,
+
+    #[arg(
+        long = "git-ignore",
+        help = "do not list the files ignored by git, such as the ones matching '.gitignore'"
+    )]
+    git_ignore: bool,
+
+    #[arg(
+        long = "git",
+        help = "show the git status of each file in the details, like 'git status --porcelain'"
+    )]
+    git: bool,
+
+    #[arg(
+        short = 'Z',
+        long = "context",
+        help = "show the SELinux security context of each file in the details"
+    )]
+    context: bool,
+
+    #[arg(
+        long = "quoting-style",
+        value_enum,
+        value_name = "WORD",
+        default_value = "literal",
+        help = "quote the names of files in the style: literal, shell, shell-escape or c"
+    )]
+    quoting_style: QuotingStyle,
+
+    #[arg(
+        long = "full-path",
+        overrides_with = "absolute_path",
+        help = "show the path of each file as it was given instead of its name, the tree is not affected"
+    )]
+    full_path: bool,
+
+    #[arg(
+        long = "absolute-path",
+        overrides_with = "full_path",
+        help = "show the absolute path of each file instead of its name, the tree is not affected"
+    )]
+    absolute_path: bool,
+
+    #[arg(
+        long = "summary",
+        help = "print the counts of the listed files and their total size at the end"
+    )]
+    summary: bool,
+
+    #[arg(
+        long = "legend",
+        help = "print the key of the colors of the file types at the end, only when the output is colored"
+    )]
+    legend: bool,
+
+    // There is no short name for it, because '-v' is taken by the natural sort.
+    #[arg(
+        long = "verbose",
+        help = "print the status of the command and how long it takes to stderr"
+    )]
+    verbose: bool,
+
+    #[arg(
+        long = "config",
+        value_name = "PATH",
+        help = "read the default options from PATH instead of ~/.config/nls/config.toml"
+    )]
+    config_path: Option<PathBuf>,
+
+    #[arg(
+        long = "generate-completions",
+        value_enum,
+        value_name = "SHELL",
+        help = "print the completion script of the SHELL and exit"
+    )]
+    generate_completions: Option<Shell>,
+
+    #[arg(
+        long = "generate-man",
+        help = "print the man page in roff format and exit"
     )]
-    depth: Option<u8>,
+    generate_man: bool,
+
+    #[arg(long = "help", action = ArgAction::Help, help = "print help")]
+    help: Option<bool>,
 
     // This is a hidden field，it will not be shown in help message,
     // but it can be used to store the status of the command.
@@ -90,13 +521,16 @@ struct LsCli {
     // This field just like a state machine to show the status of the command,
     // and to instruct the program what to do next.
     // 'ls'                     => status-0 : default status
-    // 'ls -l'                  => status-1 : show details of files and directories
-    // 'ls -a'                  => status-2 : show hidden files and directories
+    // 'ls -l' or 'ls -n'       => status-1 : show details of files and directories, '-n' shows the numeric ids of owner and group
+    // 'ls -o' or 'ls -g'       => status-1 : the same as 'ls -l', but the group or the owner column is left out
+    // 'ls -a' or 'ls -A'       => status-2 : show hidden files and directories, '-a' shows '.' and '..' as well
     // 'ls -a -l'               => status-3 : calculated by 1 | 2, it will show details of all hidden files and directories
-    // 'ls -H'                  => status-4 : set status to 4, but do nothing, don't ask why, Linux ls command also do nothing when get '-h' option
-    // 'ls -l -H'               => status-5 : calculated by 1 | 4, it will show details of files and directories with human readable file sizes
-    // 'ls -a -l -H'            => status-7 : calculated by 1 | 2 | 4, it will show details of all hidden files and directories with human readable file sizes
-    // 'ls -t' of 'ls --tree'   => status-8 : show files and directories as a tree
+    // 'ls -h' or 'ls --si'     => status-4 : set status to 4, but do nothing, don't ask why, Linux ls command also do nothing when get '-h' option
+    // 'ls -l -h'               => status-5 : calculated by 1 | 4, it will show details of files and directories with human readable file sizes
+    // 'ls -a -l -h'            => status-7 : calculated by 1 | 2 | 4, it will show details of all hidden files and directories with human readable file sizes
+    // 'ls -T' or 'ls --tree'   => status-8 : show files and directories as a tree, it can be combined with 1, 2 and 4
+    // 'ls -R'                  => status-16: list subdirectories recursively, it can be combined with 1, 2 and 4
+    // 'ls --json'              => status-32: print files as JSON, it prints the tree as nested JSON when combined with 8
     // other command            => status-0 : default status
     // Above status were set by the parse function what we implemented in the impl code block.
     //
@@ -105,404 +539,693 @@ struct LsCli {
     #[arg(skip)]
     status: u8,
 
-    // Store files and directories info that from the 'get_files_and_dirs' function.
+    // Whether to colorize the output, it is decided by the 'set_color' function.
+    #[arg(skip)]
+    colorize: bool,
+
+    // Whether the output is a terminal, it is checked once in 'execute'.
+    // Colors, icons, links and the grid are only turned on by default for a terminal,
+    // so the output piped to other programs is plain text with one name per line.
+    #[arg(skip)]
+    tty: bool,
+
+    // Whether some of the paths can't be listed, nls exits with status code 2 like GNU ls.
     #[arg(skip)]
-    files: Vec<FileInfo>,
+    failed: bool,
+
+    // The default options read from the config file.
+    #[arg(skip)]
+    config: Config,
+}
+
+// A directory given on the command line.
+struct DirArg {
+    // The info is named after the path as it was typed, to sort the directories by it.
+    info: FileInfo,
+    path: PathBuf,
+    // The path as it was typed, the headers are based on it.
+    display_path: PathBuf,
+}
+
+impl Borrow<FileInfo> for DirArg {
+    fn borrow(&self) -> &FileInfo {
+        &self.info
+    }
 }
 
 impl Cli for LsCli {
     // Execute the command
-    fn execute(&mut self) {
-        // Check if the path is exist.
-        if self.path.is_none() {
-            let msg = format!("Error: path is not exist").red();
-            panic!("{}", msg);
-        } else {
-            // If the path is exist, get the canonical path
-            // Convert the path to an absolute path because the path may be a relative path.
-            // The relative path may cause panic when use fs::PathBuf.file_name() would return None.
-            self.path = Some(self.path.as_ref().unwrap().canonicalize().unwrap());
+    fn execute(&mut self) -> io::Result<()> {
+        // Nothing is listed when the completion script is asked for.
+        if let Some(shell) = self.generate_completions {
+            clap_complete::generate(shell, &mut LsCli::command(), "nls", &mut io::stdout());
+            return Ok(());
         }
 
-        self.set_status();
-        // Get files and directories info from the target path, and store them to the vec.
-        self.get_files_and_dirs();
-
-        let _v = match self.get_status() {
-            0 | 2 | 4 => self.show_names(),
-            1 | 3 | 5 | 7 => self.show_infos(),
-            8 => self.show_as_tree(),
-            _ => self.show_names(),
-        };
-    }
-}
+        // The man page is rendered from the same definition, packagers can save it as 'nls.1'.
+        if self.generate_man {
+            return clap_mangen::Man::new(LsCli::command().name("nls")).render(&mut io::stdout());
+        }
 
-impl LsCli {
-    // Set status of the command
-    fn set_status(&mut self) {
-        // Set status to 0 by default
-        self.status = 0;
+        self.config = match &self.config_path {
+            Some(path) => Config::load(path)?,
+            None => Config::load_default()?,
+        };
+        self.apply_config();
+        self.tty = io::stdout().is_terminal();
 
-        // Set status to 1 if get '-l' option
-        if self.long {
-            self.status |= 1;
+        let start = Instant::now();
+        self.set_status();
+        self.set_color();
+        let opts = self.list_options();
+        let renderer = Renderer::new(&opts);
+
+        // The diagnostics go to stderr, so they never get mixed with the listing.
+        if self.verbose {
+            eprintln!("nls: status: {}", self.get_status());
+            eprintln!("nls: paths: {:?}", self.paths);
         }
+        // Every write to the stdout is flushed at the end of the line, that is a syscall per file,
+        // so the output is buffered and written in large chunks instead.
+        let mut out = BufWriter::new(io::stdout().lock());
+
+        // Just like GNU ls, the arguments that are not directories are listed together first,
+        // then each directory is listed under a 'path:' header when there are more arguments.
+        // The tree is drawn for every argument in the given order instead.
+        let mut paths = Vec::new();
+        for path in &self.paths {
+            let path = match expand_path(path) {
+                Ok(path) => path,
+                Err(err) => {
+                    eprintln!("nls: cannot access '{}': {}", path.display(), err);
+                    self.failed = true;
+                    continue;
+                }
+            };
 
-        // Set status to 2 if get '-a' option
-        if self.all {
-            self.status |= 2;
+            match expand_glob(&path) {
+                Some(matches) if matches.is_empty() => {
+                    eprintln!("nls: {}: no matches found", path.display());
+                    self.failed = true;
+                }
+                Some(matches) => paths.extend(matches),
+                None => paths.push(path),
+            }
         }
 
-        // Set status to 4 if get '-H' option
-        if self.human_readable {
-            self.status |= 4;
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+        // The listings of several paths are put into one JSON object keyed by the paths as they were typed,
+        // so the output is still one document.
+        let mut json_object = serde_json::Map::new();
+        let keyed_json = self.json && paths.len() > 1;
+        for path in &paths {
+            // Just like GNU ls, the links to directories on the command line are followed,
+            // unless the details, the indicators or the directories themselves are asked for.
+            // '--dereference-command-line' and '-L' follow all of them.
+            let follow_args = self.dereference_command_line || self.dereference;
+            let follow_dirs =
+                follow_args || !(self.get_status() & 1 == 1 || self.classify || self.directory);
+            let canonical_path = match resolve_path(path, follow_dirs) {
+                Ok(canonical_path) => canonical_path,
+                Err(err) => {
+                    eprintln!(
+                        "nls: cannot access '{}': {}",
+                        path.display(),
+                        describe_error(&err)
+                    );
+                    self.failed = true;
+                    continue;
+                }
+            };
+
+            // The path is only resolved to a directory when it should be listed as one.
+            let is_dir = canonical_path.symlink_metadata().is_ok_and(|m| m.is_dir());
+            let listed = if self.tree || (is_dir && !self.directory) {
+                // The directories are sorted by the paths as they were typed.
+                get_file_info(&canonical_path).map(|mut info| {
+                    info.name = path.to_string_lossy().into_owned();
+                    dirs.push(DirArg {
+                        info,
+                        path: canonical_path,
+                        display_path: path.clone(),
+                    });
+                })
+            } else {
+                // The link keeps its own name, but it is shown as its target when it is followed.
+                let opts = ListOptions {
+                    dereference: follow_args,
+                    ..opts.clone()
+                };
+                list_dir(&canonical_path, &opts).map(|infos| {
+                    if keyed_json {
+                        // The path is a file here, so it is listed as exactly one info.
+                        for info in infos {
+                            json_object.insert(path.to_string_lossy().into_owned(), json!(info));
+                        }
+                        return;
+                    }
+                    // The file arguments are not in one directory, so their full paths are shown as their names.
+                    files.extend(infos.into_iter().map(|mut info| {
+                        match opts.full_path {
+                            Some(FullPath::Relative) => {
+                                info.name = path.to_string_lossy().into_owned()
+                            }
+                            Some(FullPath::Absolute) => {
+                                info.name = info.path.to_string_lossy().into_owned()
+                            }
+                            None => {}
+                        }
+                        info
+                    }))
+                })
+            };
+            if let Err(err) = listed {
+                eprintln!("nls: {}", err);
+                self.failed = true;
+            }
         }
+        sort_files(&mut files, &opts);
+        sort_files(&mut dirs, &opts);
 
-        if self.tree {
-            self.status |= 8;
+        if !files.is_empty() {
+            if self.json {
+                renderer.show_json(&files, &mut out)?;
+            } else {
+                renderer.show_files(&files, &mut out)?;
+            }
         }
-    }
 
-    // Get status of the command
-    fn get_status(&self) -> u8 {
-        self.status
-    }
+        if keyed_json {
+            for dir in &dirs {
+                let listed = if self.tree {
+                    build_tree(&dir.path, &opts).map(|tree| json!(tree))
+                } else {
+                    list_dir(&dir.path, &opts)
+                        .map(|files| json!(files))
+                        .map_err(io::Error::from)
+                };
+                match listed {
+                    Ok(value) => {
+                        json_object.insert(dir.display_path.to_string_lossy().into_owned(), value);
+                    }
+                    Err(err) => {
+                        eprintln!("nls: {}", err);
+                        self.failed = true;
+                    }
+                }
+            }
+            serde_json::to_writer_pretty(&mut out, &json_object)?;
+            writeln!(out)?;
+            // Nothing else is shown, there is no header or blank line in JSON.
+            dirs.clear();
+        }
 
-    // Show files and directories as a tree.
-    fn show_as_tree(&mut self) {
-        let cur_path = self.path.as_ref().unwrap();
-        self.show_as_tree_recursively(cur_path, 0);
-    }
+        let headers = paths.len() > 1;
+        for (i, dir) in dirs.iter().enumerate() {
+            if i > 0 || !files.is_empty() {
+                writeln!(out)?;
+            }
 
-    // Show files and directories as a tree recursively.
-    #[cfg(unix)]
-    fn show_as_tree_recursively(&self, path: &std::path::PathBuf, depth: u8) {
-        if !path.exists() {
-            println!(
-                "{:indent$}| - {}",
-                "",
-                "No such file or directory".red(),
-                indent = (depth * 5) as usize
-            );
-            return;
+            match self.show_dir(&renderer, &dir.path, &dir.display_path, headers, &mut out) {
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Err(err),
+                // The other directories are still listed when one of them can't be read.
+                Err(err) => {
+                    eprintln!("nls: {}", err);
+                    self.failed = true;
+                }
+                Ok(()) => {}
+            }
         }
 
-        if depth > self.depth.unwrap() {
-            return;
-        }
+        // The subdirectories that can't be read have been reported by the recursive listing.
+        self.failed |= renderer.failed();
 
-        // Get file info.
-        let file_info = self.get_file_info(path);
+        // JSON has no room for the footer.
+        if self.summary && !self.json {
+            writeln!(out)?;
+            renderer.show_summary(&mut out)?;
+        }
 
-        // Get file name with color.
-        let file_name_with_color = self.color_file_names(&file_info);
+        if self.legend {
+            renderer.show_legend(&mut out)?;
+        }
 
-        // Print file name with color.
-        println!(
-            "{:indent$}| - {}",
-            "",
-            file_name_with_color,
-            indent = (depth * 5) as usize
-        );
+        out.flush()?;
 
-        // If the file is a directory, get all files and directories in it.
-        if file_info.file_type == FileType::Dir {
-            let paths = match fs::read_dir(path) {
-                Ok(paths) => paths,
-                Err(_) => {
-                    println!(
-                        "{:indent$}| - {}",
-                        "",
-                        "Permission denied".red(),
-                        indent = (depth * 5) as usize
-                    );
-                    return;
-                }
-            };
-            for path in paths {
-                let path = path.unwrap().path();
-                self.show_as_tree_recursively(&path, depth + 1);
-            }
+        if self.verbose {
+            eprintln!("nls: done in {:?}", start.elapsed());
         }
-    }
 
-    // If don't get any option or use other options that don't define,
-    // just show non-hidden files name.
-    fn show_names(&self) {
-        for file in self.files.iter() {
-            if !self.all && file.is_hidden {
-                continue;
-            }
+        Ok(())
+    }
+}
 
-            print!("{:<20}", self.color_file_names(&file));
+impl LsCli {
+    // Fill the options that are not given on the command line with the config file.
+    fn apply_config(&mut self) {
+        let config = &self.config;
+        self.long |= config.long.unwrap_or(false);
+        self.human_readable |= config.human_readable.unwrap_or(false);
+        // '-a' and '-A' override each other, so the config file only counts when neither is given.
+        if !self.all && !self.almost_all {
+            self.all = config.all.unwrap_or(false);
+            self.almost_all = !self.all && config.almost_all.unwrap_or(false);
+        }
+        let sorted = self.sort_by_size
+            || self.sort_by_time
+            || self.sort_by_extension
+            || self.sort_by_version
+            || self.unsorted;
+        if self.sort.is_none() && !sorted {
+            self.sort = config.sort;
         }
-        // Add a new line at the end of the output.
-        println!();
+        self.depth = self.depth.or(config.depth.map(Depth::Levels));
     }
 
-    // Show details of files and directories
-    fn show_infos(&self) {
-        for file in self.files.iter() {
-            if !self.all && file.is_hidden {
-                continue;
-            }
+    // List a directory given on the command line, or draw the tree of any path.
+    fn show_dir(
+        &self,
+        renderer: &Renderer,
+        path: &Path,
+        display_path: &Path,
+        header: bool,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let status = self.get_status();
+        match status {
+            8..=15 => return renderer.show_as_tree(path, out),
+            40..=63 if self.tree => return renderer.show_json_tree(path, out),
+            _ => {}
+        }
 
-            let size = if self.human_readable {
-                self.human_readable_size(file.size)
-            } else {
-                file.size.to_string()
-            };
+        // The recursive listing has headers of its own, and JSON has none.
+        if header && !matches!(status, 16..=23 | 32..=63) {
+            writeln!(out, "{}:", display_path.display())?;
+        }
 
-            let file_name_with_color = self.color_file_names(&file);
-
-            println!(
-                "{} {:>3} {:>8} {:>8} {:>8} {:>20} {}",
-                file.permissions,
-                file.link,
-                file.owner,
-                file.group,
-                size,
-                file.modified_time,
-                file_name_with_color
-            );
+        // A huge directory is shown as it is read when the names go one per line unsorted,
+        // every other layout needs all of the files first.
+        let opts = renderer.options();
+        let streaming = matches!(status, 0 | 2 | 4)
+            && opts.sort == SortBy::None
+            && !opts.reverse
+            && !opts.group_directories_first
+            && opts.width.is_none()
+            && !opts.commas;
+        if streaming {
+            let files = iter_dir(path, opts)?;
+            return renderer
+                .in_dir(display_path)
+                .show_names_streaming(files, out);
         }
-    }
 
-    // Color file name by file type when show file names.
-    fn color_file_names(&self, file: &FileInfo) -> ColoredString {
-        match file.file_type {
-            FileType::File => file.name.white(),
-            FileType::Dir => file.name.cyan(),
-            FileType::Link => file.name.blue(),
-            FileType::CharDevice | FileType::BlockDevice | FileType::Fifo | FileType::Socket => {
-                file.name.green()
+        let files = list_dir(path, opts)?;
+        let renderer = renderer.in_dir(display_path);
+        match status {
+            0 | 2 | 4 => renderer.show_names(&files, out),
+            1 | 3 | 5 | 7 => {
+                renderer.show_total(&files, out)?;
+                renderer.show_infos(&files, out)
             }
+            16..=23 => renderer.show_recursively(path, display_path, &files, out),
+            32..=63 => renderer.show_json(&files, out),
+            _ => renderer.show_names(&files, out),
         }
     }
 
-    // Turn file size to human readable size.
-    fn human_readable_size(&self, size: u64) -> String {
-        let mut size = size as f64;
-        let mut unit = "B";
+    // Set status of the command
+    fn set_status(&mut self) {
+        // Set status to 0 by default
+        self.status = 0;
 
-        if size > 1024.0 {
-            size /= 1024.0;
-            unit = "K";
+        // Set status to 1 if get '-l' option, '-n', '-o', '-g' and '--full-time' imply '-l' just like GNU ls,
+        // and '--author' implies it as well.
+        let long = self.long
+            || self.numeric_uid_gid
+            || self.no_group
+            || self.no_owner
+            || self.full_time
+            || self.author;
+        if long {
+            self.status |= 1;
         }
 
-        if size > 1024.0 {
-            size /= 1024.0;
-            unit = "M";
+        // Set status to 2 if get '-a' or '-A' option
+        if self.all || self.almost_all {
+            self.status |= 2;
         }
 
-        if size > 1024.0 {
-            size /= 1024.0;
-            unit = "G";
+        // Set status to 4 if get '-h' or '--si' option
+        if self.human_readable || self.si {
+            self.status |= 4;
         }
 
-        if size > 1024.0 {
-            size /= 1024.0;
-            unit = "T";
+        if self.tree {
+            self.status |= 8;
         }
 
-        if size > 1024.0 {
-            size /= 1024.0;
-            unit = "P";
+        // Set status to 16 if get '-R' option,
+        // but there is nothing to recurse into when the directory itself is listed by '-d'.
+        if self.recursive && !self.directory {
+            self.status |= 16;
         }
 
-        format!("{:.2}{}", size, unit)
+        // Set status to 32 if get '--json' option
+        if self.json {
+            self.status |= 32;
+        }
     }
 
-    // Just print files and dirs name in the path
-    #[cfg(unix)]
-    fn get_files_and_dirs(&mut self) {
-        // Get PathBuf of file.
-        let path_buf: &std::path::PathBuf = self.path.as_ref().unwrap();
-
-        // Check if the path is a file.
-        if !path_buf.is_dir() {
-            // If it is a file, just get file info and return.
-            self.files.push(self.get_file_info(path_buf));
-            return;
-        } else {
-            // If it is a directory, get all files and directories in it.
-            // And store them to the vec.
-            let paths = match fs::read_dir(path_buf) {
-                Ok(paths) => paths,
-                Err(_) => {
-                    let msg = format!("Error: Permission denied").red();
-                    panic!("{}", msg);
-                }
-            };
-            for path in paths {
-                let path = path.unwrap().path();
-                self.files.push(self.get_file_info(&path));
-            }
-        }
+    // Decide whether to colorize the output by the '--color' option.
+    // The colored crate is overridden as well, so that '--color=always' still works
+    // when the output is not a terminal.
+    //
+    // 'auto' also honors the NO_COLOR convention (https://no-color.org):
+    // a present and non-empty NO_COLOR turns colors off, but '--color=always' still wins.
+    fn set_color(&mut self) {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        self.colorize = match self.color {
+            When::Always => true,
+            When::Never => false,
+            When::Auto => !no_color && self.tty,
+        };
+        colored::control::set_override(self.colorize);
+    }
 
-        // Sort by option
-        if self.sort_by_size {
-            self.files.sort_by(|f1, f2| f1.size.cmp(&f2.size));
+    // Get the key to sort files, the '--sort' option wins over the short options.
+    fn sort_by(&self) -> SortBy {
+        if let Some(sort) = self.sort {
+            sort
+        } else if self.unsorted {
+            SortBy::None
+        } else if self.sort_by_size {
+            SortBy::Size
         } else if self.sort_by_time {
-            self.files
-                .sort_by(|f1, f2: &FileInfo| f1.modified_time.cmp(&f2.modified_time));
+            SortBy::Time
+        } else if self.sort_by_extension {
+            SortBy::Extension
+        } else if self.sort_by_version {
+            SortBy::Version
         } else {
-            self.files.sort_by(|f1, f2| f1.name.cmp(&f2.name));
+            SortBy::Name
         }
+    }
 
-        // Reverse sort if get '-r' option.
-        if self.resort {
-            self.files.reverse();
+    // Get the time to show and sort on, the '--time' option wins over '-u' and '-c'.
+    fn time_field(&self) -> TimeField {
+        if let Some(time) = self.time {
+            time
+        } else if self.access_time {
+            TimeField::Atime
+        } else if self.change_time {
+            TimeField::Ctime
+        } else {
+            TimeField::Mtime
         }
     }
 
-    // Get file info, such as file size, modified time, etc.
-    #[cfg(unix)]
-    fn get_file_info(&self, path_buf: &std::path::PathBuf) -> FileInfo {
-        // Get file metadata, include file size, modified time, etc.
-        let metadata = match fs::symlink_metadata(path_buf) {
-            Ok(metadata) => metadata,
-            Err(_) => path_buf.metadata().unwrap(),
-        };
-
-        // Get file basic info include: permissions, type, name and is not hidden.
-        let (permission, file_type) = self.analysis_mode(&metadata);
+    // Get status of the command
+    fn get_status(&self) -> u8 {
+        self.status
+    }
 
-        // Get file name and judge if it is hidden.
-        let file_name = path_buf.file_name().unwrap().to_string_lossy().to_string();
-        let is_hidden = file_name.starts_with(".");
+    // Turn the parsed arguments to the options of the library.
+    fn list_options(&self) -> ListOptions {
+        ListOptions {
+            all: self.all,
+            almost_all: self.almost_all,
+            ignore: self.ignore.clone(),
+            hide: self.hide.clone(),
+            filter: self.filter.clone(),
+            grep: self.grep.clone(),
+            grep_invert: self.grep_invert,
+            git_ignore: self.git_ignore,
+            sort: self.sort_by(),
+            ignore_case: self.ignore_case,
+            reverse: self.resort,
+            group_directories_first: self.group_directories_first,
+            directory: self.directory,
+            dereference: self.dereference,
+            long: self.get_status() & 1 == 1,
+            allocated_size: self.allocated_size,
+            total_size: self.total_size,
+            human_readable: self.human_readable,
+            si: self.si,
+            bytes: self.bytes,
+            // '--block-size' wins over '-k'.
+            block_size: self
+                .block_size
+                .or(self.kibibytes.then_some(BlockSize::Bytes(1024))),
+            time: self.time_field(),
+            time_style: if self.full_time {
+                TimeStyle::FullIso
+            } else {
+                self.time_style.clone()
+            },
+            relative_time: self.relative_time,
+            octal_permissions: self.octal_permissions,
+            numeric_uid_gid: self.numeric_uid_gid,
+            author: self.author,
+            no_owner: self.no_owner,
+            no_group: self.no_group,
+            git: self.git,
+            context: self.context,
+            inode: self.inode,
+            classify: self.classify,
+            slash_dirs: self.slash_dirs,
+            quoting_style: self.quoting_style,
+            full_path: if self.absolute_path {
+                Some(FullPath::Absolute)
+            } else if self.full_path {
+                Some(FullPath::Relative)
+            } else {
+                None
+            },
+            // Just like GNU ls, list one file per line when the output is not a terminal,
+            // so that the output is easy to be handled by other programs.
+            // A width given by '-w' lays the names out even then, and 0 puts them on one line.
+            // '-x' and '-C' always lay them out, in the 'COLUMNS' or 80 columns when there is no terminal.
+            width: match self.width {
+                _ if self.one_per_line => None,
+                Some(0) => Some(usize::MAX),
+                Some(width) => Some(width),
+                None if self.across || self.columns => {
+                    terminal_width().or_else(columns_from_env).or(Some(80))
+                }
+                None if self.tty => terminal_width(),
+                None => None,
+            },
+            commas: self.commas,
+            across: self.across,
+            depth: self.depth.unwrap_or_default(),
+            dirs_only: self.dirs_only,
+            mounts: self.mounts,
+            one_file_system: self.one_file_system,
+            max_entries: self.max_entries,
+            ascii: self.ascii,
+            // JSON is never colored.
+            color: self.colorize && !self.json,
+            // The hint would be taken for a file by the scripts reading the output.
+            empty_hint: self.tty && !self.json,
+            icons: !self.json
+                && match self.icons {
+                    When::Always => true,
+                    When::Never => false,
+                    When::Auto => self.tty,
+                },
+            // 'auto' gives no links when colors are turned off by '--color=never'.
+            hyperlink: !self.json
+                && match self.hyperlink {
+                    When::Always => true,
+                    When::Never => false,
+                    When::Auto => self.color != When::Never && self.tty,
+                },
+            ls_colors: {
+                let mut colors = LsColors::from_env();
+                self.config.apply_colors(&mut colors);
+                colors
+            },
+            threads: self
+                .threads
+                .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get())),
+        }
+    }
+}
 
-        // println!("{}", format!("{} - {}", file_name, permission).red());
+// Expand a leading '~' or '~user' to the home directory, and '$VAR' or '${VAR}' to the value
+// of the variable, like a shell does. They are left as they are when nls is not run by a shell,
+// e.g. from the config of an editor. An existing path is never expanded.
+fn expand_path(path: &Path) -> Result<PathBuf, String> {
+    let Some(text) = path.to_str() else {
+        return Ok(path.to_path_buf());
+    };
+    if !text.contains(['~', '$']) || path.symlink_metadata().is_ok() {
+        return Ok(path.to_path_buf());
+    }
 
-        // Get file link number.
-        let link_num = metadata.nlink();
+    let text = expand_tilde(text)?;
+    Ok(PathBuf::from(expand_vars(&text)?))
+}
 
-        // Get modified time of file.
-        let modify_time: DateTime<Local> = metadata.modified().unwrap().into();
-        let modify_time = modify_time.format("%Y-%m-%d %H:%M:%S").to_string();
+// Only a leading '~' is expanded, '~user' is looked up in the user database.
+fn expand_tilde(text: &str) -> Result<String, String> {
+    let Some(rest) = text.strip_prefix('~') else {
+        return Ok(text.to_string());
+    };
+
+    let end = rest
+        .find(['/', std::path::MAIN_SEPARATOR])
+        .unwrap_or(rest.len());
+    let (user, rest) = rest.split_at(end);
+    let home = if user.is_empty() {
+        env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .map(PathBuf::from)
+            .ok_or("the home directory is unknown")?
+    } else {
+        home_dir_of(user).ok_or_else(|| format!("no such user '{}'", user))?
+    };
+
+    Ok(format!("{}{}", home.to_string_lossy(), rest))
+}
 
-        // Get owner and group name.
-        let (owner_name, group_name) = self.get_owner_and_group_name(&metadata, &file_type);
+#[cfg(unix)]
+fn home_dir_of(user: &str) -> Option<PathBuf> {
+    use users::os::unix::UserExt;
 
-        // Store these infos to FileInfo struct and add it to vec.
-        let fi = FileInfo {
-            permissions: permission,
-            file_type: file_type,
-            link: link_num,
-            owner: owner_name,
-            group: group_name,
-            size: metadata.len(),
-            modified_time: modify_time,
-            name: file_name,
-            is_hidden,
-        };
+    users::get_user_by_name(user).map(|user| user.home_dir().to_path_buf())
+}
 
-        fi
-    }
+// There is no user database to look up on Windows.
+#[cfg(windows)]
+fn home_dir_of(_user: &str) -> Option<PathBuf> {
+    None
+}
 
-    // Get owner and group name.
-    #[cfg(unix)]
-    fn get_owner_and_group_name(
-        &self,
-        metadata: &fs::Metadata,
-        file_type: &FileType,
-    ) -> (String, String) {
-        let group_name: String;
-
-        let uid = metadata.uid();
-        let gid = metadata.gid();
-
-        // If the file type is not file, dir or link, just one way to get group name by libc.
-        // It's so difficult to get group name by std::os::unix::fs::MetadataExt and users crate.
-        // Because The method in the 'user crate' for converting a gid to a group name
-        // can cause the program to panic due to memory alignment issues.
-        // So it is necessary to use libc to call the C language implementation to accomplish this functionality.
-        if file_type != &FileType::File
-            || file_type != &FileType::Dir
-            || file_type != &FileType::Link
-        {
-            // 获取用户组名
-            let group_info = unsafe { getgrgid(gid) };
-            group_name = if !group_info.is_null() {
-                let group_name_cstr = unsafe { CStr::from_ptr((*group_info).gr_name) };
-                group_name_cstr.to_string_lossy().into_owned()
-            } else {
-                "".to_string()
+// A '$' that is not followed by a name is kept, an unset variable is an error,
+// because listing '/bin' for '$UNSET/bin' would be a surprise.
+fn expand_vars(text: &str) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = text;
+
+    while let Some(at) = rest.find('$') {
+        expanded.push_str(&rest[..at]);
+        let after = &rest[at + 1..];
+
+        let (name, next) = match after.strip_prefix('{') {
+            Some(braced) => {
+                let end = braced
+                    .find('}')
+                    .ok_or_else(|| format!("missing '}}' in '{}'", text))?;
+                (&braced[..end], &braced[end + 1..])
             }
-        } else {
-            group_name = get_group_by_gid(gid)
-                .map(|g| g.name().to_string_lossy().into_owned())
-                .unwrap_or_else(|| "Unknown".to_string());
-        }
-
-        let owner_name = get_user_by_uid(uid)
-            .map(|u| u.name().to_string_lossy().into_owned())
-            .unwrap_or_else(|| "Unknown".to_string());
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
 
-        // println!("{} - {}", owner_name, group_name);
+        if name.is_empty() {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
 
-        return (owner_name, group_name);
+        let value = env::var(name).map_err(|_| format!("the variable '{}' is not set", name))?;
+        expanded.push_str(&value);
+        rest = next;
     }
+    expanded.push_str(rest);
 
-    // Analysis file mode from metadata.
-    #[cfg(unix)]
-    fn analysis_mode(&self, metadata: &fs::Metadata) -> (String, FileType) {
-        // Get file permissions.
-        let mode: u32 = metadata.permissions().mode();
-
-        // Turn permission number to string.
-        let perms_str = format!(
-            "{}{}{}",
-            self.turn_permission_num_to_str((mode >> 6) & 0o007),
-            self.turn_permission_num_to_str((mode >> 3) & 0o007),
-            self.turn_permission_num_to_str(mode & 0o007)
-        );
-
-        // Get file type, and add it to the msg.
-        let file_type = metadata.file_type();
-        let result = match file_type {
-            _ if file_type.is_dir() => (format!("d{perms_str}"), FileType::Dir),
-            _ if file_type.is_file() => (format!("-{perms_str}"), FileType::File),
-            _ if file_type.is_symlink() => (format!("l{perms_str}"), FileType::Link),
-            _ if file_type.is_char_device() => (format!("c{perms_str}"), FileType::CharDevice),
-            _ if file_type.is_block_device() => (format!("b{perms_str}"), FileType::BlockDevice),
-            _ if file_type.is_fifo() => (format!("p{perms_str}"), FileType::Fifo),
-            _ if file_type.is_socket() => (format!("s{perms_str}"), FileType::Socket),
-            _ => (format!("?{perms_str}"), FileType::File),
-        };
+    Ok(expanded)
+}
 
-        result
+// Expand the path like a shell does when it is a glob pattern but not an existing path,
+// e.g. 'nls "*.rs"' run without a shell. None means the path is taken as it is.
+fn expand_glob(path: &Path) -> Option<Vec<PathBuf>> {
+    let pattern = path.to_str()?;
+    if !pattern.contains(['*', '?', '[']) || path.symlink_metadata().is_ok() {
+        return None;
     }
 
-    // Turn permission number to string.
-    // For example: 0o755 => rwxr-xr-x
-    #[cfg(unix)]
-    fn turn_permission_num_to_str(&self, num: u32) -> String {
-        let mut result = String::from("");
+    // Just like a shell, the hidden files are only matched by a leading '.' in the pattern.
+    let options = MatchOptions {
+        require_literal_leading_dot: true,
+        ..MatchOptions::new()
+    };
+    let matches = glob::glob_with(pattern, options).ok()?;
+    Some(matches.filter_map(Result::ok).collect())
+}
 
-        if num & 4 == 4 {
-            result.push_str("r");
-        } else {
-            result.push_str("-");
-        }
+// Make the path absolute, because the relative path such as '.' has no file name.
+// A symbolic link is resolved except for itself, so it is shown as the link,
+// and a broken link can still be shown although its target is missing.
+// The links to directories are followed when 'follow_dirs' is set, so their contents are listed.
+fn resolve_path(path: &Path, follow_dirs: bool) -> io::Result<PathBuf> {
+    let metadata = path.symlink_metadata()?;
+    if !metadata.file_type().is_symlink() || (follow_dirs && path.is_dir()) {
+        return path.canonicalize();
+    }
 
-        if num & 2 == 2 {
-            result.push_str("w");
-        } else {
-            result.push_str("-");
-        }
+    let Some(name) = path.file_name() else {
+        return path.canonicalize();
+    };
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize()?,
+        _ => env::current_dir()?,
+    };
+    Ok(parent.join(name))
+}
 
-        if num & 1 == 1 {
-            result.push_str("x");
-        } else {
-            result.push_str("-");
+// Split the value of NLS_OPTIONS into arguments like a shell does, but only the quotes are
+// understood: the spaces inside '...' or "..." are kept, and there is no escaping.
+fn split_options(options: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+
+    for c in options.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => args.extend(current.take()),
+            (None, c) => current.get_or_insert_with(String::new).push(c),
         }
-
-        result
     }
+    args.extend(current);
+
+    args
 }
 
 fn main() {
-    let mut ls = LsCli::parse();
-    ls.execute();
+    // The default options go right after the program name, so the flags typed later win.
+    let mut args: Vec<OsString> = env::args_os().collect();
+    if let Ok(options) = env::var("NLS_OPTIONS") {
+        let at = args.len().min(1);
+        args.splice(
+            at..at,
+            split_options(&options).into_iter().map(OsString::from),
+        );
+    }
+
+    let mut ls = LsCli::parse_from(args);
+    match ls.execute() {
+        // Stop quietly when the reader of the output has gone, e.g. 'nls | head'.
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {}
+        // The paths that can't be listed have been reported already.
+        Ok(()) if ls.failed => std::process::exit(2),
+        Err(err) => {
+            // Exit with status code 2 just like GNU ls when it meets serious trouble.
+            eprintln!("nls: {}", err);
+            std::process::exit(2);
+        }
+        Ok(()) => {}
+    }
 }
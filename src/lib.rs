@@ -1,3 +1,5 @@
+pub mod ls;
+
 pub trait Cli {
-    fn execute(&mut self);
-}
\ No newline at end of file
+    fn execute(&mut self) -> std::io::Result<()>;
+}
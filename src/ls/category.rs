@@ -0,0 +1,90 @@
+use colored::Color;
+
+use super::{file_extension, FileInfo, FileType};
+
+// The kinds of regular files told apart by their extensions, they are tinted like the common setups of dircolors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Archive,
+    Image,
+    Audio,
+    Video,
+    Code,
+}
+
+impl Category {
+    // Get the color of the category, and whether it is bold.
+    pub fn color(self) -> (Color, bool) {
+        match self {
+            Category::Archive => (Color::Red, true),
+            Category::Image => (Color::Magenta, true),
+            Category::Audio => (Color::BrightCyan, false),
+            Category::Video => (Color::BrightMagenta, false),
+            Category::Code => (Color::BrightYellow, false),
+        }
+    }
+}
+
+// The categories of files by their extensions in lower case, sorted by the extension.
+const EXTENSION_CATEGORIES: [(&str, Category); 47] = [
+    ("7z", Category::Archive),
+    ("aac", Category::Audio),
+    ("avi", Category::Video),
+    ("bmp", Category::Image),
+    ("bz2", Category::Archive),
+    ("c", Category::Code),
+    ("cpp", Category::Code),
+    ("deb", Category::Archive),
+    ("flac", Category::Audio),
+    ("gif", Category::Image),
+    ("go", Category::Code),
+    ("gz", Category::Archive),
+    ("h", Category::Code),
+    ("hpp", Category::Code),
+    ("ico", Category::Image),
+    ("jar", Category::Archive),
+    ("java", Category::Code),
+    ("jpeg", Category::Image),
+    ("jpg", Category::Image),
+    ("js", Category::Code),
+    ("m4a", Category::Audio),
+    ("mkv", Category::Video),
+    ("mov", Category::Video),
+    ("mp3", Category::Audio),
+    ("mp4", Category::Video),
+    ("ogg", Category::Audio),
+    ("opus", Category::Audio),
+    ("png", Category::Image),
+    ("py", Category::Code),
+    ("rar", Category::Archive),
+    ("rb", Category::Code),
+    ("rpm", Category::Archive),
+    ("rs", Category::Code),
+    ("sh", Category::Code),
+    ("svg", Category::Image),
+    ("tar", Category::Archive),
+    ("tgz", Category::Archive),
+    ("tif", Category::Image),
+    ("tiff", Category::Image),
+    ("ts", Category::Code),
+    ("wav", Category::Audio),
+    ("webm", Category::Video),
+    ("webp", Category::Image),
+    ("wmv", Category::Video),
+    ("xz", Category::Archive),
+    ("zip", Category::Archive),
+    ("zst", Category::Archive),
+];
+
+// Get the category of a regular file by its extension, the other types have none.
+pub fn category(file: &FileInfo) -> Option<Category> {
+    if file.file_type != FileType::File {
+        return None;
+    }
+
+    let extension = file_extension(&file.name).to_lowercase();
+    EXTENSION_CATEGORIES
+        .binary_search_by_key(&extension.as_str(), |(ext, _)| ext)
+        .ok()
+        .map(|idx| EXTENSION_CATEGORIES[idx].1)
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use colored::Color;
+use serde::Deserialize;
+
+use super::{describe_error, LsColors, SortBy};
+
+// The default options of nls read from the config file, e.g. '~/.config/nls/config.toml':
+//
+//     long = true
+//     human_readable = true
+//     sort = "size"
+//     depth = 3
+//
+//     [colors]
+//     directory = "bold magenta"
+//     executable = "01;32"
+//
+// Every field can be left out, and the command line always wins over the config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub long: Option<bool>,
+    pub all: Option<bool>,
+    pub almost_all: Option<bool>,
+    pub human_readable: Option<bool>,
+    pub sort: Option<SortBy>,
+    pub depth: Option<u8>,
+
+    // The colors of file types, they win over the 'LS_COLORS' variable.
+    pub colors: HashMap<ThemeKey, ThemeColor>,
+}
+
+// The file types that can be colored by the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemeKey {
+    Directory,
+    File,
+    Symlink,
+    BrokenSymlink,
+    Executable,
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+}
+
+impl ThemeKey {
+    // The key of the same file type in 'LS_COLORS'.
+    fn ls_colors_key(self) -> &'static str {
+        match self {
+            ThemeKey::Directory => "di",
+            ThemeKey::File => "fi",
+            ThemeKey::Symlink => "ln",
+            ThemeKey::BrokenSymlink => "or",
+            ThemeKey::Executable => "ex",
+            ThemeKey::Fifo => "pi",
+            ThemeKey::Socket => "so",
+            ThemeKey::BlockDevice => "bd",
+            ThemeKey::CharDevice => "cd",
+        }
+    }
+}
+
+// The SGR code of a color in the config file.
+// It is written as a name such as 'blue', 'bold bright red', or as the code itself such as '01;34'.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ThemeColor(String);
+
+impl FromStr for ThemeColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit() || c == ';') {
+            return Ok(ThemeColor(s.to_string()));
+        }
+
+        let (bold, name) = match s.strip_prefix("bold ") {
+            Some(name) => (true, name.trim()),
+            None => (false, s),
+        };
+        let color = Color::from_str(name).map_err(|_| format!("invalid color '{}'", s))?;
+
+        Ok(ThemeColor(if bold {
+            format!("01;{}", color.to_fg_str())
+        } else {
+            color.to_fg_str().into_owned()
+        }))
+    }
+}
+
+impl TryFrom<String> for ThemeColor {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Config {
+    // '$XDG_CONFIG_HOME/nls/config.toml', or '~/.config/nls/config.toml' when it is not set.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config_home.join("nls").join("config.toml"))
+    }
+
+    // Read the default config file, nothing is set when it is missing.
+    pub fn load_default() -> io::Result<Self> {
+        match Config::default_path() {
+            Some(path) if path.exists() => Config::load(&path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    // Read the config file at the path, it is an error when the file is missing or broken.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!(
+                    "cannot read config '{}': {}",
+                    path.display(),
+                    describe_error(&err)
+                ),
+            )
+        })?;
+
+        Config::parse(&text).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid config '{}': {}", path.display(), err),
+            )
+        })
+    }
+
+    // Parse the TOML text of the config file.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|err| err.message().to_string())
+    }
+
+    // Put the colors of the config file over the ones of 'LS_COLORS'.
+    pub fn apply_colors(&self, colors: &mut LsColors) {
+        for (key, color) in &self.colors {
+            colors.set_type(key.ls_colors_key(), &color.0);
+        }
+    }
+}
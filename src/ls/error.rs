@@ -0,0 +1,65 @@
+use std::{error::Error, fmt, io, path::PathBuf};
+
+use super::describe_error;
+
+// The errors of reading files and directories, they are shown just like GNU ls does.
+#[derive(Debug)]
+pub enum NlsError {
+    // The metadata of the file can't be read, e.g. the file is removed during the listing.
+    Access { path: PathBuf, source: io::Error },
+
+    // The content of the directory can't be read, e.g. the permission is denied.
+    OpenDir { path: PathBuf, source: io::Error },
+
+    // An entry of the directory can't be read, e.g. the network mount is flaky.
+    ReadDir { path: PathBuf, source: io::Error },
+}
+
+impl NlsError {
+    // Get the io error that causes it.
+    pub fn io_error(&self) -> &io::Error {
+        match self {
+            NlsError::Access { source, .. }
+            | NlsError::OpenDir { source, .. }
+            | NlsError::ReadDir { source, .. } => source,
+        }
+    }
+}
+
+impl fmt::Display for NlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NlsError::Access { path, source } => write!(
+                f,
+                "cannot access '{}': {}",
+                path.display(),
+                describe_error(source)
+            ),
+            NlsError::OpenDir { path, source } => write!(
+                f,
+                "cannot open directory '{}': {}",
+                path.display(),
+                describe_error(source)
+            ),
+            NlsError::ReadDir { path, source } => write!(
+                f,
+                "reading directory '{}': {}",
+                path.display(),
+                describe_error(source)
+            ),
+        }
+    }
+}
+
+impl Error for NlsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.io_error())
+    }
+}
+
+// The kind of the io error is kept, so the callers can still tell what happened.
+impl From<NlsError> for io::Error {
+    fn from(err: NlsError) -> Self {
+        io::Error::new(err.io_error().kind(), err.to_string())
+    }
+}
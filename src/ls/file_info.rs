@@ -0,0 +1,549 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Once,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(unix)]
+use std::time::Duration;
+
+#[cfg(windows)]
+use std::os::windows::fs::MetadataExt;
+#[cfg(unix)]
+use std::{
+    ffi::CStr,
+    os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt},
+};
+
+use chrono::{DateTime, Local};
+#[cfg(unix)]
+use libc::getgrgid;
+use serde::{Serialize, Serializer};
+
+use super::{NlsError, TimeField};
+#[cfg(unix)]
+use users::{get_group_by_gid, get_user_by_uid};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileType {
+    File,
+    Dir,
+    Link,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    Socket,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct FileInfo {
+    pub inode: u64,
+    pub file_type: FileType,
+    pub permissions: String,
+    pub mode: u32,
+    pub link: u64,
+    pub owner: String,
+    pub group: String,
+
+    // The raw ids of the owner and the group, they are shown instead of the names with '-n'.
+    pub uid: u32,
+    pub gid: u32,
+
+    pub size: u64,
+
+    // The number of 512-byte blocks allocated to the file.
+    pub blocks: u64,
+
+    // The device that the block or char device file stands for, it is 0 for the other types.
+    pub rdev: u64,
+
+    #[serde(serialize_with = "serialize_time")]
+    pub modified_time: SystemTime,
+
+    // The last time the file was read.
+    #[serde(serialize_with = "serialize_time")]
+    pub accessed_time: SystemTime,
+
+    // The last time the metadata of the file was changed, such as the permissions.
+    #[serde(serialize_with = "serialize_time")]
+    pub changed_time: SystemTime,
+
+    // The time the file was created, it is None when the platform or the filesystem doesn't record it.
+    #[serde(serialize_with = "serialize_optional_time")]
+    pub created_time: Option<SystemTime>,
+
+    pub name: String,
+    pub is_hidden: bool,
+
+    // Where the symbolic link points to, it is None for the other types.
+    pub link_target: Option<PathBuf>,
+
+    // The symbolic link points to a path that does not exist.
+    pub is_broken_link: bool,
+
+    // The directory is on another filesystem than its parent, so something is mounted on it.
+    // It is set by the listing, which reads the device of the parent once for all of its entries.
+    pub is_mount: bool,
+
+    // The device that the file is on, it is 0 on Windows.
+    #[serde(skip)]
+    pub device: u64,
+
+    // The path that the info is read from, it is not a part of JSON.
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl FileInfo {
+    // Get the time of the file chosen by '--time', it is shown and sorted on.
+    pub fn time(&self, field: TimeField) -> SystemTime {
+        match field {
+            TimeField::Mtime => self.modified_time,
+            TimeField::Atime => self.accessed_time,
+            TimeField::Ctime => self.changed_time,
+            TimeField::Birth => self.created_time.unwrap_or_else(|| {
+                // Warn only once, or every file without the birth time gets a line.
+                static WARNING: Once = Once::new();
+                WARNING.call_once(|| {
+                    eprintln!("nls: the birth time is not supported here, using the modified time")
+                });
+                self.modified_time
+            }),
+        }
+    }
+
+    // Get the bytes allocated to the file on the disk, the 'size' is the apparent size.
+    // A sparse file takes less than its apparent size, and a small file takes a whole block.
+    pub fn allocated_size(&self) -> u64 {
+        self.blocks * 512
+    }
+
+    // Get the major and minor numbers of the device, they are shown instead of the size of device files.
+    #[cfg(unix)]
+    pub fn device_numbers(&self) -> (u64, u64) {
+        let rdev = self.rdev as libc::dev_t;
+        (libc::major(rdev) as u64, libc::minor(rdev) as u64)
+    }
+
+    // There are no device files on Windows.
+    #[cfg(windows)]
+    pub fn device_numbers(&self) -> (u64, u64) {
+        (0, 0)
+    }
+}
+
+// The names of the users and groups that have been looked up, by their ids.
+// Thousands of files in a directory usually belong to a few users, so every id is looked up only once.
+// None is kept for the ids without a name, so they are not looked up again either.
+// There are no names to look up on Windows, so the cache is never read there.
+#[derive(Debug, Default)]
+#[cfg_attr(windows, allow(dead_code))]
+pub struct NameCache {
+    users: HashMap<u32, Option<String>>,
+    groups: HashMap<u32, Option<String>>,
+}
+
+// Serialize the timestamp as a RFC 3339 string in local time, e.g. '2024-01-02T03:04:05.678+08:00'.
+fn serialize_time<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    let time: DateTime<Local> = (*time).into();
+    serializer.serialize_str(&time.to_rfc3339())
+}
+
+// The missing time is null in JSON.
+fn serialize_optional_time<S: Serializer>(
+    time: &Option<SystemTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serialize_time(time, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+// Get file info, such as file size, modified time, etc.
+// It is an error when the metadata of the file can't be read.
+pub fn get_file_info(path: &Path) -> Result<FileInfo, NlsError> {
+    get_file_info_with_cache(path, &mut NameCache::default(), false)
+}
+
+// Read the metadata of the file, the symbolic link is followed when 'dereference' is set,
+// but a broken link can't be followed, so its own metadata is used then.
+fn read_metadata(path: &Path, dereference: bool) -> Result<fs::Metadata, NlsError> {
+    let metadata = if dereference {
+        fs::metadata(path).or_else(|_| fs::symlink_metadata(path))
+    } else {
+        fs::symlink_metadata(path)
+    };
+
+    metadata.map_err(|source| NlsError::Access {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+// Get file info, the names of the owner and group are looked up through the cache.
+// The symbolic link is shown as its target when 'dereference' is set.
+#[cfg(unix)]
+pub fn get_file_info_with_cache(
+    path: &Path,
+    cache: &mut NameCache,
+    dereference: bool,
+) -> Result<FileInfo, NlsError> {
+    // Get file metadata, include file size, modified time, etc.
+    let metadata = read_metadata(path, dereference)?;
+
+    // Get file basic info include: permissions, type, name and is not hidden.
+    // The marker of extended attributes is read only when the permissions are shown, see 'xattr_marker'.
+    let (permission, file_type) = analysis_mode(&metadata);
+
+    // Get file name and judge if it is hidden.
+    // The root directory has no file name, so the whole path is used.
+    let file_name = path
+        .file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .to_string();
+    let is_hidden = file_name.starts_with('.');
+
+    // Get file link number.
+    let link_num = metadata.nlink();
+
+    // Get modified time of file.
+    // It is kept as the raw timestamp, and will be formatted when it is shown.
+    // Some filesystems don't record it, then the epoch is shown.
+    let modify_time = metadata.modified().unwrap_or(UNIX_EPOCH);
+
+    // Get owner and group name.
+    let (owner_name, group_name) = get_owner_and_group_name(&metadata, &file_type, cache);
+
+    // Get the target of the symbolic link, and check if it exists.
+    // 'Path::exists' follows the link, so it is false when the target is missing.
+    let link_target = match file_type {
+        FileType::Link => fs::read_link(path).ok(),
+        _ => None,
+    };
+    let is_broken_link = link_target.is_some() && !path.exists();
+
+    // Store these infos to FileInfo struct and add it to vec.
+    Ok(FileInfo {
+        inode: metadata.ino(),
+        permissions: permission,
+        mode: metadata.permissions().mode(),
+        file_type,
+        link: link_num,
+        owner: owner_name,
+        group: group_name,
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        size: metadata.len(),
+        blocks: metadata.blocks(),
+        rdev: metadata.rdev(),
+        modified_time: modify_time,
+        accessed_time: unix_time(metadata.atime(), metadata.atime_nsec()),
+        changed_time: unix_time(metadata.ctime(), metadata.ctime_nsec()),
+        // It is an error on the platforms and filesystems without the birth time.
+        created_time: metadata.created().ok(),
+        name: file_name,
+        path: path.to_path_buf(),
+        is_hidden,
+        link_target,
+        is_broken_link,
+        is_mount: false,
+        device: metadata.dev(),
+    })
+}
+
+// Get file info on Windows, there are no owner, group, inode and links like Unix,
+// so they are shown as '-' or 0, and the mode is made up from the read-only attribute.
+// The cache is not used, because there are no names to look up.
+#[cfg(windows)]
+pub fn get_file_info_with_cache(
+    path: &Path,
+    _cache: &mut NameCache,
+    dereference: bool,
+) -> Result<FileInfo, NlsError> {
+    let metadata = read_metadata(path, dereference)?;
+    let modified_time = metadata.modified().unwrap_or(UNIX_EPOCH);
+
+    let (permission, file_type) = analysis_mode(&metadata);
+
+    let file_name = path
+        .file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .to_string();
+    // Files with the hidden attribute are hidden too, not only the dotfiles.
+    let is_hidden =
+        file_name.starts_with('.') || metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0;
+
+    let mode = match (metadata.permissions().readonly(), &file_type) {
+        (true, FileType::Dir) => 0o555,
+        (false, FileType::Dir) => 0o755,
+        (true, _) => 0o444,
+        (false, _) => 0o644,
+    };
+
+    let link_target = match file_type {
+        FileType::Link => fs::read_link(path).ok(),
+        _ => None,
+    };
+    let is_broken_link = link_target.is_some() && !path.exists();
+
+    Ok(FileInfo {
+        inode: 0,
+        permissions: permission,
+        mode,
+        file_type,
+        link: 1,
+        owner: "-".to_string(),
+        group: "-".to_string(),
+        uid: 0,
+        gid: 0,
+        size: metadata.file_size(),
+        blocks: metadata.file_size().div_ceil(512),
+        rdev: 0,
+        modified_time,
+        accessed_time: metadata.accessed().unwrap_or(modified_time),
+        // There is no change time on Windows, the modified time is the closest one.
+        changed_time: modified_time,
+        created_time: metadata.created().ok(),
+        name: file_name,
+        path: path.to_path_buf(),
+        is_hidden,
+        link_target,
+        is_broken_link,
+        // The drives and the mounted folders are not told apart from the directories yet.
+        is_mount: false,
+        device: 0,
+    })
+}
+
+// Check if the directory is a mount point, just like the 'mountpoint' command:
+// its device differs from the device of its parent.
+pub fn is_mount_point(file: &FileInfo, parent_device: Option<u64>) -> bool {
+    file.file_type == FileType::Dir && parent_device.is_some_and(|device| device != file.device)
+}
+
+// Get the device that the directory is on, it is read once for all of its entries to find the mount points.
+#[cfg(unix)]
+pub fn device_of(dir: &Path) -> Option<u64> {
+    fs::metadata(dir).ok().map(|metadata| metadata.dev())
+}
+
+// The mount points are not told apart on Windows.
+#[cfg(windows)]
+pub fn device_of(_dir: &Path) -> Option<u64> {
+    None
+}
+
+// Get the marker of the extended attributes to put after the permissions, just like GNU ls and macOS ls.
+// '+' means the file has an ACL, '@' means it has the other extended attributes.
+// The SELinux context is left out, because almost every file has it on SELinux systems, and '-Z' shows it.
+// Listing the attributes takes a syscall, so it's only done for the long listing.
+#[cfg(unix)]
+pub fn xattr_marker(path: &Path, dereference: bool) -> Option<char> {
+    let names = if dereference {
+        xattr::list_deref(path)
+    } else {
+        xattr::list(path)
+    };
+
+    let mut marker = None;
+    for name in names.ok()? {
+        match name.to_str() {
+            Some("system.posix_acl_access" | "system.posix_acl_default") => return Some('+'),
+            Some("security.selinux") => {}
+            _ => marker = Some('@'),
+        }
+    }
+    marker
+}
+
+// There are no extended attributes on Windows.
+#[cfg(windows)]
+pub fn xattr_marker(_path: &Path, _dereference: bool) -> Option<char> {
+    None
+}
+
+// Get the SELinux security context of the file from its 'security.selinux' extended attribute.
+// None means the file has no context, or the system doesn't support extended attributes.
+#[cfg(unix)]
+pub fn security_context(path: &Path, dereference: bool) -> Option<String> {
+    let value = if dereference {
+        xattr::get_deref(path, "security.selinux")
+    } else {
+        xattr::get(path, "security.selinux")
+    };
+
+    // The context is stored as a C string, so it ends with a NUL.
+    let value = value.ok()??;
+    Some(
+        String::from_utf8_lossy(&value)
+            .trim_end_matches('\0')
+            .to_string(),
+    )
+}
+
+// There is no SELinux on Windows.
+#[cfg(windows)]
+pub fn security_context(_path: &Path, _dereference: bool) -> Option<String> {
+    None
+}
+
+// Turn the seconds and nanoseconds since the epoch to the timestamp, the seconds are negative before 1970.
+#[cfg(unix)]
+fn unix_time(secs: i64, nsecs: i64) -> SystemTime {
+    let nsecs = Duration::from_nanos(nsecs as u64);
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64) + nsecs
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()) + nsecs
+    }
+}
+
+// Get owner and group name.
+#[cfg(unix)]
+fn get_owner_and_group_name(
+    metadata: &fs::Metadata,
+    file_type: &FileType,
+    cache: &mut NameCache,
+) -> (String, String) {
+    let uid = metadata.uid();
+    let gid = metadata.gid();
+
+    // If the file type is not file, dir or link, just one way to get group name by libc.
+    // It's so difficult to get group name by std::os::unix::fs::MetadataExt and users crate.
+    // Because The method in the 'user crate' for converting a gid to a group name
+    // can cause the program to panic due to memory alignment issues.
+    // So it is necessary to use libc to call the C language implementation to accomplish this functionality.
+    let group_name = match file_type {
+        FileType::CharDevice | FileType::BlockDevice | FileType::Fifo | FileType::Socket => cache
+            .groups
+            .entry(gid)
+            .or_insert_with(|| {
+                // 获取用户组名
+                let group_info = unsafe { getgrgid(gid) };
+                if !group_info.is_null() {
+                    let group_name_cstr = unsafe { CStr::from_ptr((*group_info).gr_name) };
+                    Some(group_name_cstr.to_string_lossy().into_owned())
+                } else {
+                    None
+                }
+            })
+            .clone()
+            .unwrap_or_default(),
+        FileType::File | FileType::Dir | FileType::Link => cache
+            .groups
+            .entry(gid)
+            .or_insert_with(|| {
+                get_group_by_gid(gid).map(|g| g.name().to_string_lossy().into_owned())
+            })
+            .clone()
+            .unwrap_or_else(|| "Unknown".to_string()),
+    };
+
+    let owner_name = cache
+        .users
+        .entry(uid)
+        .or_insert_with(|| get_user_by_uid(uid).map(|u| u.name().to_string_lossy().into_owned()))
+        .clone()
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    (owner_name, group_name)
+}
+
+// Analysis file mode from metadata.
+#[cfg(unix)]
+pub fn analysis_mode(metadata: &fs::Metadata) -> (String, FileType) {
+    // Get file permissions.
+    let mode: u32 = metadata.permissions().mode();
+
+    // Turn permission number to string.
+    // The setuid, setgid and sticky bits are folded into the execute positions of
+    // the owner, group and others, just like GNU ls.
+    let perms_str = format!(
+        "{}{}{}",
+        turn_permission_num_to_str((mode >> 6) & 0o007, (mode & 0o4000 != 0).then_some('s')),
+        turn_permission_num_to_str((mode >> 3) & 0o007, (mode & 0o2000 != 0).then_some('s')),
+        turn_permission_num_to_str(mode & 0o007, (mode & 0o1000 != 0).then_some('t'))
+    );
+
+    // Get file type, and add it to the msg.
+    let file_type = metadata.file_type();
+    match file_type {
+        _ if file_type.is_dir() => (format!("d{perms_str}"), FileType::Dir),
+        _ if file_type.is_file() => (format!("-{perms_str}"), FileType::File),
+        _ if file_type.is_symlink() => (format!("l{perms_str}"), FileType::Link),
+        _ if file_type.is_char_device() => (format!("c{perms_str}"), FileType::CharDevice),
+        _ if file_type.is_block_device() => (format!("b{perms_str}"), FileType::BlockDevice),
+        _ if file_type.is_fifo() => (format!("p{perms_str}"), FileType::Fifo),
+        _ if file_type.is_socket() => (format!("s{perms_str}"), FileType::Socket),
+        _ => (format!("?{perms_str}"), FileType::File),
+    }
+}
+
+#[cfg(windows)]
+const FILE_ATTRIBUTE_READONLY: u32 = 0x1;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x20;
+
+// Analysis file attributes from metadata on Windows, they are shown like the 'Mode' of PowerShell.
+// For example: a read-only and hidden file => '-arh-'
+#[cfg(windows)]
+pub fn analysis_mode(metadata: &fs::Metadata) -> (String, FileType) {
+    let attributes = metadata.file_attributes();
+    let flag = |attribute: u32, c: char| if attributes & attribute != 0 { c } else { '-' };
+    let attributes_str = format!(
+        "{}{}{}{}",
+        flag(FILE_ATTRIBUTE_ARCHIVE, 'a'),
+        flag(FILE_ATTRIBUTE_READONLY, 'r'),
+        flag(FILE_ATTRIBUTE_HIDDEN, 'h'),
+        flag(FILE_ATTRIBUTE_SYSTEM, 's')
+    );
+
+    let file_type = metadata.file_type();
+    match file_type {
+        _ if file_type.is_symlink() => (format!("l{attributes_str}"), FileType::Link),
+        _ if file_type.is_dir() => (format!("d{attributes_str}"), FileType::Dir),
+        _ => (format!("-{attributes_str}"), FileType::File),
+    }
+}
+
+// Turn permission number to string.
+// For example: 0o755 => rwxr-xr-x
+//
+// The special char takes the execute position when the special bit is set,
+// it is in lower case when the execute bit is set too, otherwise in upper case.
+// For example: 0o4755 => rwsr-xr-x, 0o4644 => rwSr--r--
+#[cfg(unix)]
+fn turn_permission_num_to_str(num: u32, special: Option<char>) -> String {
+    let mut result = String::from("");
+
+    if num & 4 == 4 {
+        result.push('r');
+    } else {
+        result.push('-');
+    }
+
+    if num & 2 == 2 {
+        result.push('w');
+    } else {
+        result.push('-');
+    }
+
+    match special {
+        Some(c) if num & 1 == 1 => result.push(c),
+        Some(c) => result.push(c.to_ascii_uppercase()),
+        None if num & 1 == 1 => result.push('x'),
+        None => result.push('-'),
+    }
+
+    result
+}
@@ -0,0 +1,160 @@
+use std::path::{Component, Path, PathBuf};
+
+use git2::{Repository, Status, StatusOptions};
+
+// The status of the files in a git work tree, it is read once for a listing.
+// The paths are relative to the root of the work tree, just like 'git status --porcelain'.
+pub struct GitStatuses {
+    workdir: PathBuf,
+    statuses: Vec<(PathBuf, Status)>,
+}
+
+impl GitStatuses {
+    // Read the status of the work tree that the directory is in,
+    // None means the directory is not in any work tree.
+    pub fn discover(dir: &Path) -> Option<Self> {
+        let repo = Repository::discover(dir).ok()?;
+        let workdir = repo.workdir()?.canonicalize().ok()?;
+
+        // The untracked and ignored directories are reported as a whole, e.g. 'target/'.
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(true)
+            .include_ignored(true)
+            .recurse_untracked_dirs(false)
+            .recurse_ignored_dirs(false);
+        let statuses = repo
+            .statuses(Some(&mut opts))
+            .ok()?
+            .iter()
+            .filter_map(|entry| Some((PathBuf::from(entry.path()?), entry.status())))
+            .collect();
+
+        Some(GitStatuses { workdir, statuses })
+    }
+
+    // Get the two status chars of the file like 'git status --porcelain', the first one is the
+    // index and the second one is the work tree. '--' means the file is clean, and a blank
+    // status means the file is out of the work tree.
+    //
+    // A directory gets the most important status of the files in it, that is a change,
+    // then an untracked file, then an ignored file.
+    pub fn status(&self, path: &Path) -> [char; 2] {
+        // '.' in the path is dropped by the components, e.g. 'sub/.' is 'sub'.
+        let path: PathBuf = path.components().collect();
+        let Ok(relative) = path.strip_prefix(&self.workdir) else {
+            return [' ', ' '];
+        };
+        if relative.components().any(|c| c == Component::ParentDir) {
+            return [' ', ' '];
+        }
+
+        let mut chars = ['-', '-'];
+        for (status_path, status) in &self.statuses {
+            // The files in an untracked or ignored directory share its status.
+            if status_path.starts_with(relative) || relative.starts_with(status_path) {
+                let [index, worktree] = status_chars(*status);
+                if rank(index) > rank(chars[0]) {
+                    chars[0] = index;
+                }
+                if rank(worktree) > rank(chars[1]) {
+                    chars[1] = worktree;
+                }
+            }
+        }
+        chars
+    }
+}
+
+// Turn the status of git to the chars of 'git status --porcelain'.
+fn status_chars(status: Status) -> [char; 2] {
+    if status.contains(Status::WT_NEW) {
+        return ['?', '?'];
+    }
+    if status.contains(Status::IGNORED) {
+        return ['!', '!'];
+    }
+
+    let index = if status.contains(Status::INDEX_NEW) {
+        'A'
+    } else if status.contains(Status::INDEX_MODIFIED) {
+        'M'
+    } else if status.contains(Status::INDEX_DELETED) {
+        'D'
+    } else if status.contains(Status::INDEX_RENAMED) {
+        'R'
+    } else if status.contains(Status::INDEX_TYPECHANGE) {
+        'T'
+    } else {
+        '-'
+    };
+
+    let worktree = if status.contains(Status::WT_MODIFIED) {
+        'M'
+    } else if status.contains(Status::WT_DELETED) {
+        'D'
+    } else if status.contains(Status::WT_RENAMED) {
+        'R'
+    } else if status.contains(Status::WT_TYPECHANGE) {
+        'T'
+    } else {
+        '-'
+    };
+
+    [index, worktree]
+}
+
+// How important the status char is for a directory.
+fn rank(c: char) -> u8 {
+    match c {
+        '-' => 0,
+        '!' => 1,
+        '?' => 2,
+        _ => 3,
+    }
+}
+
+// The ignore rules of the git work tree that a directory is in, from '.gitignore' files,
+// '.git/info/exclude' and the global excludes file, just like git.
+pub struct GitIgnore {
+    repo: Repository,
+    workdir: PathBuf,
+}
+
+impl GitIgnore {
+    // None means the directory is not in any work tree, so nothing is ignored.
+    pub fn discover(dir: &Path) -> Option<Self> {
+        let repo = Repository::discover(dir).ok()?;
+        let workdir = repo.workdir()?.canonicalize().ok()?;
+        Some(GitIgnore { repo, workdir })
+    }
+
+    // Check if git ignores the file, the files that have been added to git are never ignored.
+    // The '.git' directory is ignored as well, git never lists it.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let path: PathBuf = path.components().collect();
+        let Ok(relative) = path.strip_prefix(&self.workdir) else {
+            return false;
+        };
+        if relative.as_os_str().is_empty()
+            || relative.components().any(|c| c == Component::ParentDir)
+        {
+            return false;
+        }
+
+        // git matches the paths with '/', and the rules like 'target/' only match directories.
+        let mut relative = relative.to_string_lossy().replace('\\', "/");
+        if is_dir {
+            relative.push('/');
+        }
+        if !self.repo.is_path_ignored(&relative).unwrap_or(false) {
+            return false;
+        }
+
+        let tracked = self.repo.index().is_ok_and(|index| {
+            index
+                .get_path(Path::new(relative.trim_end_matches('/')), 0)
+                .is_some()
+        });
+        !tracked
+    }
+}
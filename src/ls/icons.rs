@@ -0,0 +1,73 @@
+use super::{file_extension, FileInfo, FileType};
+
+// The icons are the glyphs of Nerd Fonts (https://www.nerdfonts.com),
+// the terminal must use one of these fonts to show them.
+const DIR_ICON: char = '\u{f115}';
+const LINK_ICON: char = '\u{f481}';
+const EXEC_ICON: char = '\u{f489}';
+const FILE_ICON: char = '\u{f15b}';
+const PIPE_ICON: char = '\u{f07e}';
+const SOCKET_ICON: char = '\u{f1e6}';
+const DEVICE_ICON: char = '\u{f0a0}';
+
+// The icons of files by their extensions in lower case, sorted by the extension.
+const EXTENSION_ICONS: [(&str, char); 38] = [
+    ("7z", '\u{f410}'),
+    ("bmp", '\u{f1c5}'),
+    ("c", '\u{e61e}'),
+    ("cpp", '\u{e61d}'),
+    ("css", '\u{e749}'),
+    ("csv", '\u{f1c3}'),
+    ("gif", '\u{f1c5}'),
+    ("go", '\u{e626}'),
+    ("gz", '\u{f410}'),
+    ("h", '\u{f0fd}'),
+    ("hpp", '\u{f0fd}'),
+    ("html", '\u{f13b}'),
+    ("ico", '\u{f1c5}'),
+    ("java", '\u{e256}'),
+    ("jpeg", '\u{f1c5}'),
+    ("jpg", '\u{f1c5}'),
+    ("js", '\u{e74e}'),
+    ("json", '\u{e60b}'),
+    ("lock", '\u{f023}'),
+    ("md", '\u{f48a}'),
+    ("mp3", '\u{f001}'),
+    ("mp4", '\u{f03d}'),
+    ("pdf", '\u{f1c1}'),
+    ("png", '\u{f1c5}'),
+    ("py", '\u{e606}'),
+    ("rb", '\u{e21e}'),
+    ("rs", '\u{e7a8}'),
+    ("sh", '\u{f489}'),
+    ("svg", '\u{f1c5}'),
+    ("tar", '\u{f410}'),
+    ("toml", '\u{e615}'),
+    ("ts", '\u{e628}'),
+    ("txt", '\u{f15c}'),
+    ("wav", '\u{f001}'),
+    ("xml", '\u{e619}'),
+    ("yaml", '\u{e615}'),
+    ("yml", '\u{e615}'),
+    ("zip", '\u{f410}'),
+];
+
+// Get the icon of the file, the extension of a regular file goes first,
+// and the type of the file is the last choice.
+pub fn icon(file: &FileInfo) -> char {
+    match file.file_type {
+        FileType::Dir => DIR_ICON,
+        FileType::Link => LINK_ICON,
+        FileType::Fifo => PIPE_ICON,
+        FileType::Socket => SOCKET_ICON,
+        FileType::BlockDevice | FileType::CharDevice => DEVICE_ICON,
+        FileType::File => {
+            let extension = file_extension(&file.name).to_lowercase();
+            match EXTENSION_ICONS.binary_search_by_key(&extension.as_str(), |(ext, _)| ext) {
+                Ok(idx) => EXTENSION_ICONS[idx].1,
+                Err(_) if file.mode & 0o111 != 0 => EXEC_ICON,
+                Err(_) => FILE_ICON,
+            }
+        }
+    }
+}
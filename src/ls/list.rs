@@ -0,0 +1,500 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering,
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use glob::{MatchOptions, Pattern};
+use serde::Serialize;
+
+use super::{
+    device_of, get_file_info, get_file_info_with_cache, is_mount_point, FileInfo, FileType,
+    GitIgnore, ListOptions, NameCache, NlsError, SortBy,
+};
+
+// Directories with fewer entries than this are always read by one thread,
+// because starting the threads costs more than reading the metadata of a few files.
+const PARALLEL_THRESHOLD: usize = 512;
+
+// A file in the tree, the children of a directory are nested in it.
+#[derive(Debug, Clone, Serialize)]
+pub struct TreeNode {
+    #[serde(flatten)]
+    pub info: FileInfo,
+
+    // It is None for files and for directories deeper than the depth limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<TreeNode>>,
+}
+
+// List the path, a file is listed as itself and a directory is listed as its content,
+// unless 'directory' is set to list the directory itself.
+// Hidden files in the directory are skipped unless 'all' or 'almost_all' is set,
+// the files matching the patterns of 'ignore' and 'hide' are skipped too, and the files are sorted by option.
+//
+// The files in the directory that can't be read are warned about and skipped, like GNU ls,
+// so one broken entry never aborts the whole listing.
+pub fn list_dir(path: &Path, opts: &ListOptions) -> Result<Vec<FileInfo>, NlsError> {
+    if !is_listed_as_dir(path, opts) {
+        return list_file(path, opts);
+    }
+
+    // If it is a directory, get all files and directories in it.
+    let paths = fs::read_dir(path).map_err(|source| NlsError::OpenDir {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let mut files = dot_entries(path, opts)?;
+    let paths = entry_paths(path, paths.map(|entry| entry.map(|entry| entry.path())));
+    for file in collect_file_infos(&paths, opts.threads, opts.dereference) {
+        match file {
+            Ok(file) => files.push(file),
+            Err(err) => eprintln!("nls: {}", err),
+        }
+    }
+
+    // The work tree is looked up once for the directory.
+    let git_ignore = opts.git_ignore.then(|| GitIgnore::discover(path)).flatten();
+    files.retain(|file| is_listed(file, opts, git_ignore.as_ref()));
+
+    let device = device_of(path);
+    for file in &mut files {
+        mark_mount_point(file, device);
+    }
+
+    // The total sizes are summed up before the sort, so '-S' sorts on them.
+    if opts.total_size {
+        sum_dir_sizes(&mut files);
+    }
+
+    sort_files(&mut files, opts);
+    Ok(files)
+}
+
+// List the path just like 'list_dir', but the files of a directory are read one by one as they are taken,
+// so the first ones can be shown before the rest are read, and a huge directory takes no memory for them.
+// They are in the order of the directory, because a sort needs all of them.
+pub fn iter_dir<'a>(
+    path: &Path,
+    opts: &'a ListOptions,
+) -> Result<Box<dyn Iterator<Item = FileInfo> + 'a>, NlsError> {
+    if !is_listed_as_dir(path, opts) {
+        return Ok(Box::new(list_file(path, opts)?.into_iter()));
+    }
+
+    let entries = fs::read_dir(path).map_err(|source| NlsError::OpenDir {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let dir = path.to_path_buf();
+    let mut cache = NameCache::default();
+    let files = entries.filter_map(move |entry| {
+        let entry = match entry {
+            Ok(entry) => entry.path(),
+            Err(source) => {
+                let err = NlsError::ReadDir {
+                    path: dir.clone(),
+                    source,
+                };
+                eprintln!("nls: {}", err);
+                return None;
+            }
+        };
+        match get_file_info_with_cache(&entry, &mut cache, opts.dereference) {
+            Ok(file) => Some(file),
+            Err(err) => {
+                eprintln!("nls: {}", err);
+                None
+            }
+        }
+    });
+
+    let git_ignore = opts.git_ignore.then(|| GitIgnore::discover(path)).flatten();
+    let device = device_of(path);
+    let files = dot_entries(path, opts)?
+        .into_iter()
+        .chain(files)
+        .filter(move |file| is_listed(file, opts, git_ignore.as_ref()))
+        .map(move |mut file| {
+            mark_mount_point(&mut file, device);
+            if opts.total_size {
+                sum_dir_sizes(std::slice::from_mut(&mut file));
+            }
+            file
+        });
+    Ok(Box::new(files))
+}
+
+// Check if the path is listed as its content, a symbolic link to a directory is a file unless it is followed.
+fn is_listed_as_dir(path: &Path, opts: &ListOptions) -> bool {
+    let is_dir = if opts.dereference {
+        path.is_dir()
+    } else {
+        path.symlink_metadata().is_ok_and(|m| m.is_dir())
+    };
+    is_dir && !opts.directory
+}
+
+// List a file as itself, or a directory itself for 'directory'.
+fn list_file(path: &Path, opts: &ListOptions) -> Result<Vec<FileInfo>, NlsError> {
+    let mut files = vec![get_file_info_with_cache(
+        path,
+        &mut NameCache::default(),
+        opts.dereference,
+    )?];
+    // '..' is read instead of the parent of the path, so it works for '.' and the symbolic links as well.
+    files[0].is_mount = is_mount_point(&files[0], device_of(&path.join("..")));
+    if opts.total_size {
+        sum_dir_sizes(&mut files);
+    }
+    Ok(files)
+}
+
+// Mark the file as a mount point when its device differs from the device of the directory it is listed in,
+// '.' and '..' never are.
+fn mark_mount_point(file: &mut FileInfo, dir_device: Option<u64>) {
+    file.is_mount = !is_dot_entry(&file.name) && is_mount_point(file, dir_device);
+}
+
+// 'read_dir' never yields '.' and '..', so they are made up here for 'all', just like GNU ls.
+fn dot_entries(path: &Path, opts: &ListOptions) -> Result<Vec<FileInfo>, NlsError> {
+    let mut files = Vec::new();
+    if opts.all {
+        for name in [".", ".."] {
+            let mut file = get_file_info(&path.join(name))?;
+            file.name = name.to_string();
+            files.push(file);
+        }
+    }
+    Ok(files)
+}
+
+// Check if the file of the directory is listed by the options, the hidden files are only listed with
+// 'all' or 'almost_all', and the ones matching the patterns, the filters or the ignore rules of git are left out.
+fn is_listed(file: &FileInfo, opts: &ListOptions, git_ignore: Option<&GitIgnore>) -> bool {
+    if !(opts.all || opts.almost_all) && file.is_hidden {
+        return false;
+    }
+    if is_ignored(&file.name, opts) {
+        return false;
+    }
+    if !opts.filter.is_empty() && !opts.filter.iter().any(|filter| filter.matches(file)) {
+        return false;
+    }
+    if let Some(grep) = &opts.grep {
+        if grep.is_match(&file.name) == opts.grep_invert {
+            return false;
+        }
+    }
+    match git_ignore {
+        Some(git_ignore) => {
+            is_dot_entry(&file.name)
+                || !git_ignore.is_ignored(&file.path, file.file_type == FileType::Dir)
+        }
+        None => true,
+    }
+}
+
+// Collect the paths of the entries of the directory, the entries that can't be read are warned about
+// and skipped, so the others are still listed.
+pub fn entry_paths(
+    dir: &Path,
+    entries: impl IntoIterator<Item = io::Result<PathBuf>>,
+) -> Vec<PathBuf> {
+    entries
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Ok(path) => Some(path),
+            Err(source) => {
+                let err = NlsError::ReadDir {
+                    path: dir.to_path_buf(),
+                    source,
+                };
+                eprintln!("nls: {}", err);
+                None
+            }
+        })
+        .collect()
+}
+
+// Replace the sizes of the directories with the total sizes of their content,
+// '..' is left alone, because summing up the parent directory is too much for a listing.
+fn sum_dir_sizes(files: &mut [FileInfo]) {
+    for file in files {
+        if file.file_type == FileType::Dir && file.name != ".." {
+            file.size = total_size(&file.path);
+        }
+    }
+}
+
+// Sum up the sizes of the files in the directory and all of its subdirectories,
+// the directories themselves are not counted. The symbolic links are counted as themselves and never followed,
+// and the subdirectories that can't be read are skipped.
+pub fn total_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => total_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+// Get the infos of the paths, they are split into chunks for the threads when there are many of them.
+// Every thread has its own name cache, and the infos keep the order of the paths,
+// so the result is the same as the one read by a single thread.
+fn collect_file_infos(
+    paths: &[PathBuf],
+    threads: usize,
+    dereference: bool,
+) -> Vec<Result<FileInfo, NlsError>> {
+    if threads <= 1 || paths.len() < PARALLEL_THRESHOLD {
+        let mut cache = NameCache::default();
+        return paths
+            .iter()
+            .map(|path| get_file_info_with_cache(path, &mut cache, dereference))
+            .collect();
+    }
+
+    let chunk_size = paths.len().div_ceil(threads);
+    thread::scope(|scope| {
+        let workers: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || collect_file_infos(chunk, 1, dereference)))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    })
+}
+
+// Build the tree of the path, the children of every directory are listed by 'list_dir',
+// and directories deeper than the depth limit are not descended into.
+// A directory that has been visited on the way from the root is a loop, its children are left out.
+pub fn build_tree(path: &Path, opts: &ListOptions) -> io::Result<TreeNode> {
+    let parent_device = device_of(&path.join(".."));
+    build_tree_recursively(path, opts, 0, parent_device, &mut HashSet::new())
+}
+
+fn build_tree_recursively(
+    path: &Path,
+    opts: &ListOptions,
+    depth: usize,
+    parent_device: Option<u64>,
+    ancestors: &mut HashSet<PathBuf>,
+) -> io::Result<TreeNode> {
+    let mut info = get_file_info_with_cache(path, &mut NameCache::default(), opts.dereference)?;
+    info.is_mount = is_mount_point(&info, parent_device);
+
+    // The root is listed even when it is a mount point itself.
+    let crosses_file_system = opts.one_file_system && info.is_mount && depth > 0;
+    let children = if info.file_type == FileType::Dir
+        && opts.depth.reaches(depth)
+        && !crosses_file_system
+        && !is_loop(path, ancestors)
+    {
+        // A directory that can't be read is reported and left without children,
+        // the rest of the tree is still built, just like the branches of the text tree.
+        match list_dir(path, opts) {
+            Ok(listed) => {
+                let real_path = real_path(path);
+                ancestors.insert(real_path.clone());
+
+                let mut children = Vec::new();
+                // '.' and '..' are not children of the directory, descending into them never ends.
+                for child in listed
+                    .into_iter()
+                    .filter(|child| !is_dot_entry(&child.name))
+                {
+                    match build_tree_recursively(
+                        &path.join(&child.name),
+                        opts,
+                        depth + 1,
+                        Some(info.device),
+                        ancestors,
+                    ) {
+                        Ok(node) => children.push(node),
+                        Err(err) => eprintln!("nls: {}", err),
+                    }
+                }
+
+                ancestors.remove(&real_path);
+                Some(children)
+            }
+            // The root that can't be read is an error of the whole tree like the listing.
+            Err(err) if depth == 0 => return Err(err.into()),
+            Err(err) => {
+                eprintln!("nls: {}", err);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    Ok(TreeNode { info, children })
+}
+
+// Get the real path of the directory with all links resolved, the path itself is used if it can't be resolved.
+pub(super) fn real_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Check if the directory is one of its ancestors, descending into it would never end.
+// The ancestors are the real paths of the directories on the way from the root to it.
+pub(super) fn is_loop(path: &Path, ancestors: &HashSet<PathBuf>) -> bool {
+    ancestors.contains(&real_path(path))
+}
+
+// Check if the name matches the patterns of 'ignore', or the ones of 'hide' when hidden files are not shown.
+// Just like GNU ls, a leading '.' of the name must be matched literally, so '*' doesn't match '.bashrc'.
+fn is_ignored(name: &str, opts: &ListOptions) -> bool {
+    let match_options = MatchOptions {
+        require_literal_leading_dot: true,
+        ..MatchOptions::new()
+    };
+    let matches = |patterns: &[Pattern]| {
+        patterns
+            .iter()
+            .any(|pattern| pattern.matches_with(name, match_options))
+    };
+
+    matches(&opts.ignore) || (!(opts.all || opts.almost_all) && matches(&opts.hide))
+}
+
+// Check if the name is '.' or '..', the entries that 'all' makes up for the directory itself and its parent.
+pub fn is_dot_entry(name: &str) -> bool {
+    name == "." || name == ".."
+}
+
+// Sort files by option.
+// Anything holding a 'FileInfo' can be sorted, e.g. the directories given on the command line.
+//
+// Every key has its own direction just like GNU ls, and '-r' flips it in the comparison:
+//
+//     key        natural             with '-r'
+//     name       A to Z              Z to A
+//     extension  A to Z              Z to A
+//     version    1 to 10             10 to 1
+//     size       largest first       smallest first
+//     time       newest first        oldest first
+//     none       directory order     reversed directory order
+pub fn sort_files<T: Borrow<FileInfo>>(files: &mut [T], opts: &ListOptions) {
+    let compare = |f1: &FileInfo, f2: &FileInfo| match opts.sort {
+        // The ties are broken by the name, so the order never depends on the filesystem.
+        SortBy::Size => f2
+            .size
+            .cmp(&f1.size)
+            .then_with(|| name_cmp(&f1.name, &f2.name, opts.ignore_case)),
+        SortBy::Time => f2
+            .time(opts.time)
+            .cmp(&f1.time(opts.time))
+            .then_with(|| name_cmp(&f1.name, &f2.name, opts.ignore_case)),
+        // Files without extension come first, the ties are broken by the full name.
+        SortBy::Extension => file_extension(&f1.name)
+            .cmp(file_extension(&f2.name))
+            .then_with(|| name_cmp(&f1.name, &f2.name, opts.ignore_case)),
+        SortBy::Version => natural_cmp(&f1.name, &f2.name).then_with(|| f1.name.cmp(&f2.name)),
+        SortBy::Name => name_cmp(&f1.name, &f2.name, opts.ignore_case),
+        SortBy::None => Ordering::Equal,
+    };
+
+    match opts.sort {
+        // There is nothing to compare, so the order of 'read_dir' is just reversed by '-r'.
+        SortBy::None if opts.reverse => files.reverse(),
+        SortBy::None => {}
+        _ => files.sort_by(|f1, f2| {
+            let ordering = compare(f1.borrow(), f2.borrow());
+            if opts.reverse {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        }),
+    }
+
+    // Move directories ahead of the other files.
+    // Just like GNU ls, it is applied after the sort, so directories stay first with '-r',
+    // and the stable sort keeps the chosen order within each group.
+    if opts.group_directories_first {
+        files.sort_by_key(|f| f.borrow().file_type != FileType::Dir);
+    }
+}
+
+// Compare two names byte by byte, so 'Zebra' comes before 'apple'.
+// With 'ignore_case' the case is ignored first, and the ties are broken by the bytes,
+// so 'apple' comes before 'Zebra', and the order is still the same on every run.
+fn name_cmp(a: &str, b: &str, ignore_case: bool) -> Ordering {
+    if ignore_case {
+        a.to_lowercase()
+            .cmp(&b.to_lowercase())
+            .then_with(|| a.cmp(b))
+    } else {
+        a.cmp(b)
+    }
+}
+
+// Get the extension of the file name, it is the text after the last '.'.
+// A dotfile such as '.bashrc' has no extension, so an empty string is returned.
+pub fn file_extension(name: &str) -> &str {
+    match name.rfind('.') {
+        Some(idx) if idx > 0 => &name[idx + 1..],
+        _ => "",
+    }
+}
+
+// Compare two names in natural order, the numbers within names are compared by their values,
+// so 'img2' comes before 'img10'.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (a_runs, b_runs) = (split_runs(a), split_runs(b));
+
+    for (a_run, b_run) in a_runs.iter().zip(b_runs.iter()) {
+        let is_number = |run: &str| run.starts_with(|c: char| c.is_ascii_digit());
+        let ordering = if is_number(a_run) && is_number(b_run) {
+            // Compare the numbers without leading zeros by length first, so they never overflow.
+            let (a_num, b_num) = (a_run.trim_start_matches('0'), b_run.trim_start_matches('0'));
+            a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(b_num))
+        } else {
+            a_run.cmp(b_run)
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    a_runs.len().cmp(&b_runs.len())
+}
+
+// Split the name into alternate runs of digits and the other chars.
+// For example: 'img10.png' => ['img', '10', '.png']
+fn split_runs(name: &str) -> Vec<&str> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut chars = name.char_indices().peekable();
+
+    while let Some((_, c)) = chars.next() {
+        if let Some(&(idx, next)) = chars.peek() {
+            if c.is_ascii_digit() != next.is_ascii_digit() {
+                runs.push(&name[start..idx]);
+                start = idx;
+            }
+        }
+    }
+
+    if start < name.len() {
+        runs.push(&name[start..]);
+    }
+    runs
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use super::{FileInfo, FileType};
+
+// The colors of file names set by the 'LS_COLORS' variable, just like GNU ls and dircolors.
+// For example: 'di=01;34:ln=01;36:ex=01;32:*.tar=01;31'
+#[derive(Debug, Clone, Default)]
+pub struct LsColors {
+    // The codes of file types, e.g. 'di' => '01;34'.
+    types: HashMap<String, String>,
+
+    // The suffixes of file names in lower case and their codes, e.g. '.tar' => '01;31'.
+    extensions: Vec<(String, String)>,
+}
+
+impl LsColors {
+    // Read the colors from the 'LS_COLORS' variable, nothing is set when it is missing.
+    pub fn from_env() -> Self {
+        std::env::var("LS_COLORS")
+            .map(|value| LsColors::parse(&value))
+            .unwrap_or_default()
+    }
+
+    // Parse the 'key=code' entries separated by ':', the broken entries are skipped.
+    pub fn parse(value: &str) -> Self {
+        let mut colors = LsColors::default();
+
+        for entry in value.split(':') {
+            let Some((key, code)) = entry.split_once('=') else {
+                continue;
+            };
+            if key.is_empty() || code.is_empty() {
+                continue;
+            }
+
+            match key.strip_prefix('*') {
+                Some(suffix) => colors
+                    .extensions
+                    .push((suffix.to_lowercase(), code.to_string())),
+                None => {
+                    colors.types.insert(key.to_string(), code.to_string());
+                }
+            }
+        }
+
+        colors
+    }
+
+    // Set the code of a file type, e.g. 'di' => '01;35'.
+    pub fn set_type(&mut self, key: &str, code: &str) {
+        self.types.insert(key.to_string(), code.to_string());
+    }
+
+    // Get the code to color the file name, None means 'LS_COLORS' doesn't set it.
+    //
+    // Just like GNU ls, the special kinds of regular files such as executables go before
+    // the extensions, and 'fi' is the last choice.
+    pub fn code(&self, file: &FileInfo) -> Option<&str> {
+        let code = match file.file_type {
+            FileType::Dir => {
+                let other_writable = file.mode & 0o002 != 0;
+                let sticky = file.mode & 0o1000 != 0;
+                let special = match (other_writable, sticky) {
+                    (true, true) => self.type_code("tw"),
+                    (true, false) => self.type_code("ow"),
+                    (false, true) => self.type_code("st"),
+                    (false, false) => None,
+                };
+                special.or_else(|| self.type_code("di"))
+            }
+            FileType::Link if file.is_broken_link => {
+                self.type_code("or").or_else(|| self.type_code("ln"))
+            }
+            FileType::Link => self.type_code("ln"),
+            FileType::Fifo => self.type_code("pi"),
+            FileType::Socket => self.type_code("so"),
+            FileType::BlockDevice => self.type_code("bd"),
+            FileType::CharDevice => self.type_code("cd"),
+            FileType::File if file.mode & 0o111 != 0 => self
+                .type_code("ex")
+                .or_else(|| self.extension_code(&file.name)),
+            FileType::File => self.extension_code(&file.name),
+        };
+
+        code.or_else(|| match file.file_type {
+            FileType::File => self.type_code("fi"),
+            _ => None,
+        })
+    }
+
+    // 'ln=target' means the link is colored like its target, it is left to the built-in colors.
+    fn type_code(&self, key: &str) -> Option<&str> {
+        self.types
+            .get(key)
+            .map(|code| code.as_str())
+            .filter(|code| *code != "target")
+    }
+
+    // The extensions are matched regardless of case, and the longest one wins,
+    // so '*.tar.gz' wins over '*.gz' for 'a.tar.gz'.
+    fn extension_code(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.extensions
+            .iter()
+            .filter(|(suffix, _)| name.ends_with(suffix.as_str()))
+            .max_by_key(|(suffix, _)| suffix.len())
+            .map(|(_, code)| code.as_str())
+    }
+}
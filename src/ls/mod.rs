@@ -0,0 +1,483 @@
+// The implementation of the 'nls' command.
+// Files and directories are read to 'FileInfo' by 'list_dir', and shown by the 'Renderer',
+// both of them are controlled by 'ListOptions'.
+mod category;
+mod config;
+mod error;
+mod file_info;
+mod git;
+mod icons;
+mod list;
+mod ls_colors;
+mod render;
+
+use std::{io, str::FromStr};
+
+use chrono::format::{Item, StrftimeItems};
+use clap::ValueEnum;
+use glob::Pattern;
+use regex::Regex;
+use serde::Deserialize;
+
+pub use category::{category, Category};
+pub use config::{Config, ThemeColor, ThemeKey};
+pub use error::NlsError;
+pub use file_info::{
+    analysis_mode, device_of, get_file_info, get_file_info_with_cache, is_mount_point,
+    security_context, xattr_marker, FileInfo, FileType, NameCache,
+};
+pub use git::{GitIgnore, GitStatuses};
+pub use icons::icon;
+pub use list::{
+    build_tree, entry_paths, file_extension, is_dot_entry, iter_dir, list_dir, natural_cmp,
+    sort_files, total_size, TreeNode,
+};
+pub use ls_colors::LsColors;
+pub use render::{
+    columns_from_env, group_thousands, human_readable_size, quote_name, relative_time,
+    terminal_width, visible_width, Renderer, Summary, BINARY_UNITS, SI_UNITS,
+};
+
+// The key to sort files, just like the '--sort' option of GNU ls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    #[default]
+    Name,
+    Size,
+    Time,
+    Extension,
+    Version,
+    // The entries are not sorted, they are in the order of the directory.
+    None,
+}
+
+// The types of entries to list, for the '--filter' option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FileFilter {
+    // The regular files.
+    File,
+    Dir,
+    Link,
+    // The regular files that anyone can execute.
+    Exec,
+}
+
+impl FileFilter {
+    // Check if the file is of the type.
+    pub fn matches(self, file: &FileInfo) -> bool {
+        match self {
+            FileFilter::File => file.file_type == FileType::File,
+            FileFilter::Dir => file.file_type == FileType::Dir,
+            FileFilter::Link => file.file_type == FileType::Link,
+            FileFilter::Exec => file.file_type == FileType::File && file.mode & 0o111 != 0,
+        }
+    }
+}
+
+// The time of files to show and sort on, just like the '--time' option of GNU ls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TimeField {
+    // The last modification of the content.
+    #[default]
+    #[value(alias = "modification")]
+    Mtime,
+    // The last access.
+    #[value(alias = "access", alias = "use")]
+    Atime,
+    // The last change of the metadata.
+    #[value(alias = "status")]
+    Ctime,
+    // The creation, the modified time is used when the filesystem doesn't record it.
+    #[value(alias = "creation")]
+    Birth,
+}
+
+// The path shown in place of the name of each file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FullPath {
+    // The directory as it was given joined with the name, e.g. '../dir/file'.
+    Relative,
+    // The canonical directory joined with the name, e.g. '/home/user/dir/file'.
+    Absolute,
+}
+
+// The format of the time in the details, just like the '--time-style' option of GNU ls.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TimeStyle {
+    // 2024-01-02
+    Iso,
+    // 2024-01-02 03:04:05
+    #[default]
+    LongIso,
+    // 2024-01-02 03:04:05.123456789 +0800
+    FullIso,
+    // A strftime format of chrono given by '+FORMAT'.
+    Format(String),
+}
+
+impl TimeStyle {
+    // Get the strftime format of the style.
+    pub fn format(&self) -> &str {
+        match self {
+            TimeStyle::Iso => "%Y-%m-%d",
+            TimeStyle::LongIso => "%Y-%m-%d %H:%M:%S",
+            TimeStyle::FullIso => "%Y-%m-%d %H:%M:%S%.9f %z",
+            TimeStyle::Format(format) => format,
+        }
+    }
+}
+
+impl FromStr for TimeStyle {
+    type Err = String;
+
+    // The custom format is checked here, because chrono panics when it shows a broken format.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "iso" => Ok(TimeStyle::Iso),
+            "long-iso" => Ok(TimeStyle::LongIso),
+            "full-iso" => Ok(TimeStyle::FullIso),
+            _ => match s.strip_prefix('+') {
+                Some(format) if StrftimeItems::new(format).any(|item| item == Item::Error) => {
+                    Err(format!("invalid time format '{}'", format))
+                }
+                Some(format) => Ok(TimeStyle::Format(format.to_string())),
+                None => Err(format!(
+                    "invalid time style '{}', use iso, long-iso, full-iso or +FORMAT",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+// How to quote file names, just like the '--quoting-style' option of GNU ls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum QuotingStyle {
+    // The names are shown as they are.
+    #[default]
+    Literal,
+    // The names are quoted for a shell when they need to be, e.g. 'a b'.
+    Shell,
+    // Like 'shell', and the control chars are escaped as well, e.g. 'a'$'\n''b'.
+    ShellEscape,
+    // The names are always quoted like the strings of C, e.g. "a\nb".
+    C,
+}
+
+// The unit to show sizes in, just like the '--block-size' option of GNU ls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockSize {
+    // Sizes are divided by the bytes and rounded up.
+    Bytes(u64),
+    // Human readable sizes in powers of 1024, like '-h'.
+    HumanReadable,
+    // Human readable sizes in powers of 1000, like '--si'.
+    Si,
+}
+
+impl FromStr for BlockSize {
+    type Err = String;
+
+    // The size is a number followed by a unit, either of them can be left out, e.g. '512', '1K', 'M'.
+    // 'K', 'M', 'G' ... and 'KiB', 'MiB', 'GiB' ... are powers of 1024, 'KB', 'MB', 'GB' ... are powers of 1000.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human-readable" => return Ok(BlockSize::HumanReadable),
+            "si" => return Ok(BlockSize::Si),
+            "" => return Err("the block size is empty".to_string()),
+            _ => {}
+        }
+
+        let invalid = || format!("invalid block size '{}'", s);
+        let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(digits);
+        let number: u64 = if number.is_empty() {
+            1
+        } else {
+            number.parse().map_err(|_| invalid())?
+        };
+
+        let (prefix, base) = match unit {
+            "" => ("", 1024),
+            _ if unit.ends_with("iB") => (&unit[..unit.len() - 2], 1024),
+            _ if unit.ends_with('B') => (&unit[..unit.len() - 1], 1000),
+            _ => (unit, 1024),
+        };
+        let power = match prefix {
+            "" if unit.is_empty() => 0,
+            "K" | "k" => 1,
+            "M" => 2,
+            "G" => 3,
+            "T" => 4,
+            "P" => 5,
+            "E" => 6,
+            _ => return Err(invalid()),
+        };
+
+        match number.checked_mul(u64::pow(base, power)) {
+            Some(0) | None => Err(invalid()),
+            Some(bytes) => Ok(BlockSize::Bytes(bytes)),
+        }
+    }
+}
+
+// How many levels below the listed directory the tree and the recursive listing go into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Depth {
+    // The listed directory is level 0, so 'Levels(0)' shows just its own entries.
+    Levels(u8),
+    // Go as deep as the directories go, loops are still not descended into.
+    #[default]
+    Unlimited,
+}
+
+impl Depth {
+    // Check if the directories at the level are listed, the listed directory itself is level 0.
+    pub fn reaches(self, level: usize) -> bool {
+        match self {
+            Depth::Levels(max) => level <= max as usize,
+            Depth::Unlimited => true,
+        }
+    }
+}
+
+impl FromStr for Depth {
+    type Err = String;
+
+    // The depth is a number of levels, or 'inf' for no limit at all.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inf" | "unlimited" => Ok(Depth::Unlimited),
+            _ => s
+                .parse()
+                .map(Depth::Levels)
+                .map_err(|_| format!("invalid depth '{}', expected a number or 'inf'", s)),
+        }
+    }
+}
+
+// Options to list and show files and directories.
+#[derive(Debug, Clone)]
+pub struct ListOptions {
+    // Show hidden files and directories, '.' and '..' are listed as well.
+    pub all: bool,
+
+    // Show hidden files and directories, but leave out '.' and '..'.
+    pub almost_all: bool,
+
+    // Files matching any of the patterns are never listed.
+    pub ignore: Vec<Pattern>,
+
+    // Files matching any of the patterns are not listed, unless 'all' or 'almost_all' is set.
+    pub hide: Vec<Pattern>,
+
+    // Only the files of any of the types are listed, all of them are listed when it is empty.
+    // The directories filtered out are not descended into by the tree and the recursive listing.
+    pub filter: Vec<FileFilter>,
+
+    // Only the files whose names match the regex are listed, or the ones that don't match with 'grep_invert'.
+    pub grep: Option<Regex>,
+    pub grep_invert: bool,
+
+    // Files ignored by git are never listed, e.g. the ones matching '.gitignore'.
+    pub git_ignore: bool,
+
+    // The key to sort files.
+    pub sort: SortBy,
+
+    // Compare the names regardless of case when sorting by name or extension.
+    pub ignore_case: bool,
+
+    // Reverse the order of the sort.
+    pub reverse: bool,
+
+    // Group directories before files.
+    pub group_directories_first: bool,
+
+    // List directories themselves, not their contents.
+    pub directory: bool,
+
+    // Show the targets of symbolic links instead of the links themselves,
+    // so the links to directories are descended into by the tree and the recursive listing.
+    pub dereference: bool,
+
+    // Show details of files and directories.
+    pub long: bool,
+
+    // Show the sizes allocated on the disk instead of the apparent sizes.
+    pub allocated_size: bool,
+
+    // Show the total sizes of the content of directories instead of their own sizes, like 'du'.
+    pub total_size: bool,
+
+    // Show human readable file sizes in powers of 1024, e.g. '1.46KiB'.
+    pub human_readable: bool,
+
+    // Show human readable file sizes in powers of 1000, e.g. '1.5kB', it wins over 'human_readable'.
+    pub si: bool,
+
+    // Show the sizes in bytes with thousands separators, e.g. '1,048,576', it wins over the others.
+    pub bytes: bool,
+
+    // The unit to show sizes in, it wins over 'si' and 'human_readable'.
+    pub block_size: Option<BlockSize>,
+
+    // The time to show and sort on.
+    pub time: TimeField,
+
+    // The format of the time.
+    pub time_style: TimeStyle,
+
+    // Show the time as how long ago it was, e.g. '2 hours ago', it wins over 'time_style'.
+    pub relative_time: bool,
+
+    // Show the octal mode before the permissions in the details, e.g. '0755'.
+    pub octal_permissions: bool,
+
+    // Show the numeric ids of the owner and the group instead of their names.
+    pub numeric_uid_gid: bool,
+
+    // Show the author of each file in the details, it is the same as the owner.
+    pub author: bool,
+
+    // Leave the owner column out of the details.
+    pub no_owner: bool,
+
+    // Leave the group column out of the details.
+    pub no_group: bool,
+
+    // Show the git status of each file in the details, like 'git status --porcelain'.
+    pub git: bool,
+
+    // Show the SELinux security context of each file in the details, '?' means it has none.
+    pub context: bool,
+
+    // Show the inode number of each file.
+    pub inode: bool,
+
+    // Append indicator (one of */=@|) to entries.
+    pub classify: bool,
+
+    // Append '/' indicator to directories.
+    pub slash_dirs: bool,
+
+    // How to quote the names of files and the targets of links.
+    pub quoting_style: QuotingStyle,
+
+    // Show the full paths of files instead of their names, so they can be piped to 'xargs'.
+    // The tree is not affected, its branches already tell where the files are.
+    pub full_path: Option<FullPath>,
+
+    // The width to lay names out in a grid, None means one file per line.
+    pub width: Option<usize>,
+
+    // List the names separated by commas, the lines are wrapped at the width.
+    pub commas: bool,
+
+    // Fill the grid from left to right instead of from top to bottom.
+    pub across: bool,
+
+    // The depth of the tree and the recursive listing.
+    pub depth: Depth,
+
+    // Show only the directories in the tree.
+    pub dirs_only: bool,
+
+    // Mark the mount points after their names.
+    pub mounts: bool,
+
+    // Don't descend into the mount points in the tree and the recursive listing.
+    pub one_file_system: bool,
+
+    // The most children of a directory to show in the tree, the rest are told by their number.
+    pub max_entries: Option<usize>,
+
+    // Draw the branches of the tree with ASCII chars, for the terminals without Unicode.
+    pub ascii: bool,
+
+    // Colorize the output.
+    pub color: bool,
+
+    // Show '(empty)' for a directory with nothing to list, it is meant for people, not scripts.
+    pub empty_hint: bool,
+
+    // Put the icons of Nerd Fonts before the names.
+    pub icons: bool,
+
+    // Link the names to the files by the OSC 8 escape sequences, the terminals make them clickable.
+    pub hyperlink: bool,
+
+    // The number of threads to read the metadata of large directories.
+    pub threads: usize,
+
+    // The colors of file names from 'LS_COLORS', the built-in colors are used for what it doesn't set.
+    pub ls_colors: LsColors,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        ListOptions {
+            all: false,
+            almost_all: false,
+            ignore: Vec::new(),
+            hide: Vec::new(),
+            filter: Vec::new(),
+            grep: None,
+            grep_invert: false,
+            git_ignore: false,
+            sort: SortBy::Name,
+            ignore_case: false,
+            reverse: false,
+            group_directories_first: false,
+            directory: false,
+            dereference: false,
+            long: false,
+            allocated_size: false,
+            total_size: false,
+            human_readable: false,
+            si: false,
+            bytes: false,
+            block_size: None,
+            time: TimeField::default(),
+            time_style: TimeStyle::default(),
+            relative_time: false,
+            octal_permissions: false,
+            numeric_uid_gid: false,
+            author: false,
+            no_owner: false,
+            no_group: false,
+            git: false,
+            context: false,
+            inode: false,
+            classify: false,
+            slash_dirs: false,
+            quoting_style: QuotingStyle::default(),
+            full_path: None,
+            width: None,
+            commas: false,
+            across: false,
+            depth: Depth::Unlimited,
+            dirs_only: false,
+            mounts: false,
+            one_file_system: false,
+            max_entries: None,
+            ascii: false,
+            color: false,
+            empty_hint: false,
+            icons: false,
+            hyperlink: false,
+            threads: 1,
+            ls_colors: LsColors::default(),
+        }
+    }
+}
+
+// Describe an io error like GNU ls does, without the '(os error N)' suffix that std appends.
+pub fn describe_error(err: &io::Error) -> String {
+    let msg = err.to_string();
+    match msg.find(" (os error") {
+        Some(idx) => msg[..idx].to_string(),
+        None => msg,
+    }
+}
@@ -0,0 +1,1317 @@
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    collections::HashSet,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Local};
+use colored::*;
+
+use super::{
+    build_tree, category, describe_error, device_of, get_file_info, get_file_info_with_cache, icon,
+    is_dot_entry, is_mount_point,
+    list::{is_loop, real_path},
+    list_dir, security_context, total_size, xattr_marker, BlockSize, Category, FileInfo, FileType,
+    FullPath, GitStatuses, ListOptions, NameCache, QuotingStyle,
+};
+
+// The marker of a directory that is not descended into, because it is one of its own ancestors.
+const LOOP_MARKER: &str = "[loop detected]";
+const MOUNT_MARKER: &str = "[mount point]";
+
+// The hint of a directory that has nothing to list.
+const EMPTY_HINT: &str = "(empty)";
+
+// The counts and the total size of the files that have been shown, for the footer of '--summary'.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub dirs: u64,
+    pub files: u64,
+    pub links: u64,
+    // The devices, fifos and sockets.
+    pub others: u64,
+    pub size: u64,
+}
+
+impl Summary {
+    fn add(&mut self, file: &FileInfo) {
+        match file.file_type {
+            FileType::Dir => self.dirs += 1,
+            FileType::File => self.files += 1,
+            FileType::Link => self.links += 1,
+            _ => self.others += 1,
+        }
+        self.size += file.size;
+    }
+}
+
+// Show files and directories by the options, everything is written to the given output.
+pub struct Renderer<'a> {
+    opts: &'a ListOptions,
+
+    // The name of this host in the links, it is only looked up for '--hyperlink'.
+    hostname: String,
+
+    // The directory being listed as it was given, the full paths are only shown when it is known.
+    display_dir: Option<PathBuf>,
+
+    // What has been shown so far, it is shared with the renderers of the directories.
+    summary: Rc<Cell<Summary>>,
+
+    // Some subdirectory of the recursive listing can't be read, it is shared like the summary.
+    failed: Rc<Cell<bool>>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(opts: &'a ListOptions) -> Self {
+        let hostname = if opts.hyperlink {
+            hostname()
+        } else {
+            String::new()
+        };
+        Renderer {
+            opts,
+            hostname,
+            display_dir: None,
+            summary: Rc::default(),
+            failed: Rc::default(),
+        }
+    }
+
+    // Get a renderer for the content of the directory, it is given as the display path,
+    // so the relative full paths start with it.
+    pub fn in_dir(&self, display_dir: &Path) -> Self {
+        Renderer {
+            opts: self.opts,
+            hostname: self.hostname.clone(),
+            display_dir: Some(display_dir.to_path_buf()),
+            summary: Rc::clone(&self.summary),
+            failed: Rc::clone(&self.failed),
+        }
+    }
+
+    // Check if some subdirectory of the recursive listing can't be read, the command exits with 2 then like GNU ls.
+    pub fn failed(&self) -> bool {
+        self.failed.get()
+    }
+
+    // Get the counts and the total size of the files that have been shown.
+    pub fn summary(&self) -> Summary {
+        self.summary.get()
+    }
+
+    // Show the summary of what has been shown, e.g. '3 directories, 12 files, 1 symlink, 4.2MiB total'.
+    // The sizes are formatted just like the details.
+    pub fn show_summary(&self, out: &mut impl Write) -> io::Result<()> {
+        let summary = self.summary();
+        let count =
+            |n: u64, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+        let mut parts = vec![
+            count(summary.dirs, "directory", "directories"),
+            count(summary.files, "file", "files"),
+            count(summary.links, "symlink", "symlinks"),
+        ];
+        if summary.others > 0 {
+            parts.push(count(summary.others, "special file", "special files"));
+        }
+        writeln!(
+            out,
+            "{}, {} total",
+            parts.join(", "),
+            self.format_size(summary.size)
+        )
+    }
+
+    // Show the key of the built-in colors, every type is painted just like the names of its files.
+    // Nothing is shown without colors, there is nothing to explain then.
+    pub fn show_legend(&self, out: &mut impl Write) -> io::Result<()> {
+        if !self.opts.color {
+            return Ok(());
+        }
+
+        let kinds = [
+            ("directory", FileType::Dir, 0o755, false),
+            ("file", FileType::File, 0o644, false),
+            ("executable", FileType::File, 0o755, false),
+            ("symlink", FileType::Link, 0o777, false),
+            ("broken symlink", FileType::Link, 0o777, true),
+            ("device", FileType::CharDevice, 0o644, false),
+            ("fifo", FileType::Fifo, 0o644, false),
+            ("socket", FileType::Socket, 0o755, false),
+            ("sticky directory", FileType::Dir, 0o1755, false),
+            ("other-writable directory", FileType::Dir, 0o777, false),
+            (
+                "sticky other-writable directory",
+                FileType::Dir,
+                0o1777,
+                false,
+            ),
+        ];
+        let mut kinds: Vec<String> = kinds
+            .iter()
+            .map(|(text, file_type, mode, broken)| {
+                self.paint_by_type(text, file_type, *mode, *broken, None)
+                    .to_string()
+            })
+            .collect();
+
+        let categories = [
+            ("archive", Category::Archive),
+            ("image", Category::Image),
+            ("audio", Category::Audio),
+            ("video", Category::Video),
+            ("code", Category::Code),
+        ];
+        kinds.extend(categories.iter().map(|(text, category)| {
+            self.paint_by_type(text, &FileType::File, 0o644, false, Some(*category))
+                .to_string()
+        }));
+        writeln!(out, "legend: {}", kinds.join(", "))
+    }
+
+    // Count the files that are shown for the summary.
+    fn count(&self, files: &[FileInfo]) {
+        let mut summary = self.summary.get();
+        for file in files {
+            summary.add(file);
+        }
+        self.summary.set(summary);
+    }
+
+    // Get the options that the renderer is controlled by.
+    pub fn options(&self) -> &'a ListOptions {
+        self.opts
+    }
+
+    // Show the files as names or details by the '-l' option.
+    pub fn show_files(&self, files: &[FileInfo], out: &mut impl Write) -> io::Result<()> {
+        if self.opts.long {
+            self.show_infos(files, out)
+        } else {
+            self.show_names(files, out)
+        }
+    }
+
+    // Show files and directories as a tree, the path is the root and its content are the branches.
+    // For example:
+    // root
+    // ├── sub
+    // │   └── inner.txt
+    // └── visible.txt
+    pub fn show_as_tree(&self, path: &Path, out: &mut impl Write) -> io::Result<()> {
+        if !path.exists() {
+            return writeln!(
+                out,
+                "{}",
+                self.paint("No such file or directory", Color::Red)
+            );
+        }
+
+        let mut file_info = get_file_info(path)?;
+        file_info.is_mount = is_mount_point(&file_info, device_of(&path.join("..")));
+        if !self.sums_tree_sizes() {
+            let size = match file_info.file_type {
+                FileType::Dir if self.opts.long && self.opts.total_size => total_size(path),
+                _ => file_info.size,
+            };
+            writeln!(out, "{}", self.render_tree_entry(&file_info, size))?;
+            self.show_as_tree_recursively(path, &file_info, "", 0, &mut HashSet::new(), out)?;
+            return Ok(());
+        }
+
+        // The size of the root is the sum of the whole tree, so the tree is drawn into a buffer before it.
+        let mut branches = Vec::new();
+        let content = self.show_as_tree_recursively(
+            path,
+            &file_info,
+            "",
+            0,
+            &mut HashSet::new(),
+            &mut branches,
+        )?;
+        writeln!(
+            out,
+            "{}",
+            self.render_tree_entry(&file_info, self.tree_size(&file_info, content))
+        )?;
+        out.write_all(&branches)
+    }
+
+    // The sizes of the directories in the tree are summed up from their branches with 'long',
+    // but 'total_size' has got the totals of their content already.
+    fn sums_tree_sizes(&self) -> bool {
+        self.opts.long && !self.opts.total_size
+    }
+
+    // Get the size of the entry of the tree, a directory that has been descended into gets the sum
+    // of the sizes of its branches, so only the entries shown in the tree are counted.
+    // The directories beyond the depth or on the other filesystems keep their own sizes.
+    fn tree_size(&self, file: &FileInfo, content: Option<u64>) -> u64 {
+        match content {
+            Some(content) if file.file_type == FileType::Dir => content,
+            _ => file.size,
+        }
+    }
+
+    // Show the content of the directory as the branches of the tree recursively.
+    // The prefix is drawn before the connectors of the branches, it carries the '│' of the parents
+    // that still have branches below.
+    // The ancestors are the real paths of the directories on the way from the root,
+    // a branch that leads back to one of them is marked as a loop and not descended into.
+    // It returns the total size of the branches, or None when the directory is not descended into.
+    fn show_as_tree_recursively(
+        &self,
+        path: &Path,
+        file_info: &FileInfo,
+        prefix: &str,
+        depth: usize,
+        ancestors: &mut HashSet<PathBuf>,
+        out: &mut impl Write,
+    ) -> io::Result<Option<u64>> {
+        // There are no branches when the directory itself is listed by 'directory'.
+        // The root is listed even when it is a mount point itself.
+        if file_info.file_type != FileType::Dir
+            || !self.opts.depth.reaches(depth)
+            || self.opts.directory
+            || (self.opts.one_file_system && file_info.is_mount && depth > 0)
+        {
+            return Ok(None);
+        }
+
+        let (branch, last_branch, pipe, space) = if self.opts.ascii {
+            ("|-- ", "`-- ", "|   ", "    ")
+        } else {
+            ("├── ", "└── ", "│   ", "    ")
+        };
+
+        // The children are listed by 'list_dir', so they are filtered and sorted just like the listing,
+        // and the hidden directories are not descended into without 'all' or 'almost_all'.
+        // The files are left out with 'dirs_only', like 'tree -d'.
+        let mut children: Vec<_> = match list_dir(path, self.opts) {
+            Ok(children) => children
+                .into_iter()
+                .filter(|child| !is_dot_entry(&child.name))
+                .filter(|child| !self.opts.dirs_only || child.file_type == FileType::Dir)
+                .collect(),
+            // The branch tells why the directory can't be read, e.g. 'Permission denied'.
+            Err(err) => {
+                writeln!(
+                    out,
+                    "{}{}{}",
+                    prefix,
+                    last_branch,
+                    self.paint(&describe_error(err.io_error()), Color::Red)
+                )?;
+                return Ok(None);
+            }
+        };
+        // Only the first ones by the sort are shown with 'max_entries', the rest are told by their number.
+        let more = match self.opts.max_entries {
+            Some(max) if children.len() > max => children.split_off(max).len(),
+            _ => 0,
+        };
+
+        self.count(&children);
+        // Only the root gets the hint, the empty branches are clear enough.
+        if children.is_empty() && depth == 0 && more == 0 {
+            self.show_empty_hint(&format!("{}{}", prefix, last_branch), out)?;
+            return Ok(Some(0));
+        }
+        let real_path = real_path(path);
+        ancestors.insert(real_path.clone());
+
+        let mut total = 0;
+        for (idx, child) in children.iter().enumerate() {
+            let is_last = idx + 1 == children.len() && more == 0;
+            let child_path = path.join(&child.name);
+
+            if child.file_type == FileType::Dir && is_loop(&child_path, ancestors) {
+                total += child.size;
+                writeln!(
+                    out,
+                    "{}{}{} {}",
+                    prefix,
+                    if is_last { last_branch } else { branch },
+                    self.render_tree_entry(child, child.size),
+                    self.paint(LOOP_MARKER, Color::Red)
+                )?;
+                continue;
+            }
+
+            let child_prefix = format!("{}{}", prefix, if is_last { space } else { pipe });
+            if !self.sums_tree_sizes() {
+                writeln!(
+                    out,
+                    "{}{}{}",
+                    prefix,
+                    if is_last { last_branch } else { branch },
+                    self.render_tree_entry(child, child.size)
+                )?;
+                self.show_as_tree_recursively(
+                    &child_path,
+                    child,
+                    &child_prefix,
+                    depth + 1,
+                    ancestors,
+                    out,
+                )?;
+                continue;
+            }
+
+            // The size of the directory is the sum of its branches, so they are drawn into a buffer before it.
+            let mut branches = Vec::new();
+            let content = self.show_as_tree_recursively(
+                &child_path,
+                child,
+                &child_prefix,
+                depth + 1,
+                ancestors,
+                &mut branches,
+            )?;
+            let size = self.tree_size(child, content);
+            total += size;
+
+            writeln!(
+                out,
+                "{}{}{}",
+                prefix,
+                if is_last { last_branch } else { branch },
+                self.render_tree_entry(child, size)
+            )?;
+            out.write_all(&branches)?;
+        }
+
+        ancestors.remove(&real_path);
+
+        if more > 0 {
+            let text = format!("... ({} more)", more);
+            writeln!(
+                out,
+                "{}{}{}",
+                prefix,
+                last_branch,
+                self.paint(&text, Color::BrightBlack)
+            )?;
+        }
+        Ok(Some(total))
+    }
+
+    // List the directory and all of its subdirectories, every directory gets a 'path:' header.
+    // The files are the content of the directory that has been listed.
+    pub fn show_recursively(
+        &self,
+        dir: &Path,
+        display_path: &Path,
+        files: &[FileInfo],
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        self.show_recursively_with_ancestors(dir, display_path, files, 0, &mut HashSet::new(), out)
+    }
+
+    // List the directory recursively, the ancestors are the real paths of the directories
+    // that are being listed, a subdirectory leading back to one of them is marked as a loop.
+    fn show_recursively_with_ancestors(
+        &self,
+        dir: &Path,
+        display_path: &Path,
+        files: &[FileInfo],
+        depth: usize,
+        ancestors: &mut HashSet<PathBuf>,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        // A file has nothing to recurse into, just show it.
+        if !dir.is_dir() {
+            return self.show_files(files, out);
+        }
+
+        writeln!(out, "{}:", display_path.display())?;
+        if self.opts.long {
+            self.show_total(files, out)?;
+        }
+        self.in_dir(display_path).show_files(files, out)?;
+
+        if !self.opts.depth.reaches(depth + 1) {
+            return Ok(());
+        }
+
+        // '.' and '..' listed by '-a' are never descended into,
+        // and neither are the mount points with 'one_file_system'.
+        let sub_dirs = files.iter().filter(|f| {
+            f.file_type == FileType::Dir
+                && !is_dot_entry(&f.name)
+                && !(self.opts.one_file_system && f.is_mount)
+        });
+
+        let real_path = real_path(dir);
+        ancestors.insert(real_path.clone());
+
+        for sub_dir in sub_dirs {
+            let sub_path = dir.join(&sub_dir.name);
+            let sub_display_path = display_path.join(&sub_dir.name);
+            writeln!(out)?;
+
+            if is_loop(&sub_path, ancestors) {
+                writeln!(
+                    out,
+                    "{}: {}",
+                    sub_display_path.display(),
+                    self.paint(LOOP_MARKER, Color::Red)
+                )?;
+                continue;
+            }
+
+            match list_dir(&sub_path, self.opts) {
+                Ok(sub_files) => self.show_recursively_with_ancestors(
+                    &sub_path,
+                    &sub_display_path,
+                    &sub_files,
+                    depth + 1,
+                    ancestors,
+                    out,
+                )?,
+                Err(err) => {
+                    writeln!(out, "{}:", sub_display_path.display())?;
+                    eprintln!("nls: {}", err);
+                    self.failed.set(true);
+                }
+            }
+        }
+
+        ancestors.remove(&real_path);
+
+        Ok(())
+    }
+
+    // Show the files as a JSON array, every field of them is included.
+    pub fn show_json(&self, files: &[FileInfo], out: &mut impl Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *out, files)?;
+        writeln!(out)
+    }
+
+    // Show the tree of the path as JSON, the children of directories are nested in 'children'.
+    pub fn show_json_tree(&self, path: &Path, out: &mut impl Write) -> io::Result<()> {
+        let tree = build_tree(path, self.opts)?;
+        serde_json::to_writer_pretty(&mut *out, &tree)?;
+        writeln!(out)
+    }
+
+    // If don't get any option or use other options that don't define,
+    // just show files name.
+    pub fn show_names(&self, files: &[FileInfo], out: &mut impl Write) -> io::Result<()> {
+        self.count(files);
+        if files.is_empty() {
+            return self.show_empty_hint("", out);
+        }
+        if self.opts.commas {
+            return self.show_names_with_commas(files, out);
+        }
+
+        match self.opts.width {
+            Some(width) => self.show_names_as_grid(files, width, out),
+            None => {
+                for file in files {
+                    writeln!(out, "{}", self.render_entry(file))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Show the names one per line as the files come, so nothing has to wait for the whole directory.
+    pub fn show_names_streaming(
+        &self,
+        files: impl Iterator<Item = FileInfo>,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let mut empty = true;
+        for file in files {
+            self.count(std::slice::from_ref(&file));
+            writeln!(out, "{}", self.render_entry(&file))?;
+            empty = false;
+        }
+        if empty {
+            self.show_empty_hint("", out)?;
+        }
+        Ok(())
+    }
+
+    // List the names separated by ', ' on as few lines as possible, just like 'ls -m'.
+    // A line is 80 chars wide when the width is unknown, e.g. the output is not a terminal.
+    fn show_names_with_commas(&self, files: &[FileInfo], out: &mut impl Write) -> io::Result<()> {
+        let width = self.opts.width.unwrap_or(80);
+        let mut line = String::new();
+        let mut pos = 0;
+
+        for (i, file) in files.iter().enumerate() {
+            let name = self.render_entry(file);
+            let name_width = visible_width(&name);
+
+            // The name goes to the next line when there is no room for it and a trailing comma.
+            if i > 0 {
+                if pos + 2 + name_width < width {
+                    line.push_str(", ");
+                    pos += 2;
+                } else {
+                    writeln!(out, "{},", line)?;
+                    line.clear();
+                    pos = 0;
+                }
+            }
+
+            line.push_str(&name);
+            pos += name_width;
+        }
+
+        if !files.is_empty() {
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    // Lay the names out in columns fitted to the width of the terminal,
+    // they are filled from top to bottom, or from left to right with 'across'.
+    fn show_names_as_grid(
+        &self,
+        files: &[FileInfo],
+        width: usize,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let names: Vec<String> = files.iter().map(|file| self.render_entry(file)).collect();
+
+        // The width must be measured by the visible chars of names,
+        // because the escape sequences of colors take no space on the terminal.
+        let widths: Vec<usize> = names.iter().map(|name| visible_width(name)).collect();
+        let col_width = widths.iter().max().unwrap_or(&0) + 2;
+        let cols = (width / col_width).max(1);
+        let rows = names.len().div_ceil(cols);
+
+        for row in 0..rows {
+            let mut line = String::new();
+            for col in 0..cols {
+                let (idx, next) = if self.opts.across {
+                    (row * cols + col, row * cols + col + 1)
+                } else {
+                    (col * rows + row, (col + 1) * rows + row)
+                };
+                if idx >= names.len() {
+                    break;
+                }
+
+                line.push_str(&names[idx]);
+
+                // Don't pad the last name of the line.
+                if col + 1 < cols && next < names.len() {
+                    line.push_str(&" ".repeat(col_width - widths[idx]));
+                }
+            }
+            writeln!(out, "{}", line)?;
+        }
+
+        Ok(())
+    }
+
+    // Show the hint of an empty directory after the prefix, nothing is written without 'empty_hint',
+    // so an empty directory gives no output at all.
+    fn show_empty_hint(&self, prefix: &str, out: &mut impl Write) -> io::Result<()> {
+        if self.opts.empty_hint {
+            writeln!(
+                out,
+                "{}{}",
+                prefix,
+                self.paint(EMPTY_HINT, Color::BrightBlack)
+            )?;
+        }
+        Ok(())
+    }
+
+    // Show the total blocks allocated to the files on top of the details, just like GNU ls.
+    // It is counted in 1024-byte blocks unless another block size is chosen.
+    pub fn show_total(&self, files: &[FileInfo], out: &mut impl Write) -> io::Result<()> {
+        let blocks: u64 = files.iter().map(|file| file.blocks).sum();
+        writeln!(out, "total {}", self.format_in_blocks(blocks * 512, 1024))
+    }
+
+    // Show details of files and directories
+    pub fn show_infos(&self, files: &[FileInfo], out: &mut impl Write) -> io::Result<()> {
+        self.count(files);
+        if files.is_empty() {
+            return self.show_empty_hint("", out);
+        }
+        // The inode column is right-aligned to the widest inode number.
+        let inode_width = files
+            .iter()
+            .map(|file| file.inode.to_string().len())
+            .max()
+            .unwrap_or(0);
+
+        // The permissions with the marker of extended attributes are one char longer,
+        // so the others are padded to keep the columns aligned.
+        let permissions: Vec<String> = files
+            .iter()
+            .map(|file| {
+                let mut permissions = file.permissions.clone();
+                permissions.extend(xattr_marker(&file.path, self.opts.dereference));
+                permissions
+            })
+            .collect();
+        let permissions_width = column_width(&permissions);
+
+        // The files are listed from one directory, so the work tree is looked up once.
+        let git = if self.opts.git {
+            files
+                .first()
+                .and_then(|file| file.path.parent())
+                .and_then(GitStatuses::discover)
+        } else {
+            None
+        };
+
+        // The contexts are read only when they are shown, and the column is aligned to the widest one.
+        let contexts: Vec<String> = if self.opts.context {
+            files
+                .iter()
+                .map(|file| {
+                    security_context(&file.path, self.opts.dereference)
+                        .unwrap_or_else(|| "?".to_string())
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let context_width = contexts
+            .iter()
+            .map(|c| c.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        // The sizes, the links, the owners, the groups and the times are padded to the widest ones of this listing,
+        // so the columns never jitter, whatever the units and the names are.
+        let sizes: Vec<String> = files
+            .iter()
+            .map(|file| match file.file_type {
+                // The size means nothing for device files, so the device numbers are shown like GNU ls.
+                FileType::CharDevice | FileType::BlockDevice => {
+                    let (major, minor) = file.device_numbers();
+                    format!("{}, {}", major, minor)
+                }
+                _ if self.opts.allocated_size => self.format_size(file.allocated_size()),
+                _ => self.format_size(file.size),
+            })
+            .collect();
+        let links: Vec<String> = files.iter().map(|file| file.link.to_string()).collect();
+        let (owners, groups): (Vec<String>, Vec<String>) = files
+            .iter()
+            .map(|file| {
+                if self.opts.numeric_uid_gid {
+                    (file.uid.to_string(), file.gid.to_string())
+                } else {
+                    (file.owner.clone(), file.group.clone())
+                }
+            })
+            .unzip();
+        let times: Vec<String> = files
+            .iter()
+            .map(|file| self.format_time(file.time(self.opts.time)))
+            .collect();
+        let size_width = column_width(&sizes);
+        let link_width = column_width(&links);
+        let owner_width = column_width(&owners);
+        let group_width = column_width(&groups);
+        let time_width = column_width(&times);
+
+        for (idx, file) in files.iter().enumerate() {
+            if self.opts.inode {
+                write!(out, "{:>width$} ", file.inode, width = inode_width)?;
+            }
+
+            // Show where the symbolic link points to, a broken link is shown in red.
+            // The link is marked by the indicator of its target after the arrow like GNU 'ls -lF', not by '@'.
+            let mut file_name_with_color = match file.link_target {
+                Some(_) => self.render_bare_name(file),
+                None => self.render_name(file),
+            };
+            if let Some(target) = &file.link_target {
+                let target = target.to_string_lossy();
+                let target = quote_name(&target, self.opts.quoting_style);
+                let target = if file.is_broken_link {
+                    self.paint(&target, Color::Red)
+                } else {
+                    target.normal()
+                };
+                file_name_with_color.push_str(&format!(
+                    " -> {}{}",
+                    target,
+                    self.target_indicator(file)
+                ));
+            }
+
+            // The octal mode goes before the permissions, the file type bits are left out.
+            if self.opts.octal_permissions {
+                write!(out, "{:04o} ", file.mode & 0o7777)?;
+            }
+            // The owner and group columns are dropped by '-g' and '-o',
+            // the others keep their widths, so the rows are still aligned.
+            write!(
+                out,
+                "{:<width$} {:>link_width$}",
+                permissions[idx],
+                links[idx],
+                width = permissions_width
+            )?;
+            // The names of the owner and the group are left-aligned like GNU ls.
+            // The author is the owner on Unix, there is no other author to look up.
+            if self.opts.author {
+                write!(out, " {:<owner_width$}", owners[idx])?;
+            }
+            if !self.opts.no_owner {
+                write!(out, " {:<owner_width$}", owners[idx])?;
+            }
+            if !self.opts.no_group {
+                write!(out, " {:<group_width$}", groups[idx])?;
+            }
+            if let Some(context) = contexts.get(idx) {
+                write!(out, " {:<width$}", context, width = context_width)?;
+            }
+            write!(
+                out,
+                " {:>size_width$} {:>time_width$}",
+                sizes[idx], times[idx]
+            )?;
+            if self.opts.git {
+                let status = match &git {
+                    Some(git) => git.status(&file.path),
+                    None => [' ', ' '],
+                };
+                write!(out, " {}", self.paint_git_status(status))?;
+            }
+            writeln!(out, " {}", file_name_with_color)?;
+        }
+
+        Ok(())
+    }
+
+    // Format the size in bytes of a file for display.
+    fn format_size(&self, size: u64) -> String {
+        if self.opts.bytes {
+            return group_thousands(size);
+        }
+        self.format_in_blocks(size, 1)
+    }
+
+    // Format the size in bytes in the chosen block size, the default one is used if nothing is chosen.
+    // The block size wins over '--si' that counts in powers of 1000,
+    // and '--si' wins over '-h' that counts in powers of 1024.
+    fn format_in_blocks(&self, size: u64, default: u64) -> String {
+        let block_size = match self.opts.block_size {
+            Some(block_size) => block_size,
+            None if self.opts.si => BlockSize::Si,
+            None if self.opts.human_readable => BlockSize::HumanReadable,
+            None => BlockSize::Bytes(default),
+        };
+
+        match block_size {
+            BlockSize::Bytes(bytes) => size.div_ceil(bytes).to_string(),
+            BlockSize::HumanReadable => human_readable_size(size, 1024, &BINARY_UNITS),
+            BlockSize::Si => human_readable_size(size, 1000, &SI_UNITS),
+        }
+    }
+
+    // Format the raw timestamp of a file for display.
+    fn format_time(&self, time: SystemTime) -> String {
+        if self.opts.relative_time {
+            return relative_time(time, SystemTime::now());
+        }
+
+        let time: DateTime<Local> = time.into();
+        time.format(self.opts.time_style.format()).to_string()
+    }
+
+    // Render the entry to show in the names and the tree, the inode goes before the name with '-i'.
+    fn render_entry(&self, file: &FileInfo) -> String {
+        if self.opts.inode {
+            format!("{} {}", file.inode, self.render_name(file))
+        } else {
+            self.render_name(file)
+        }
+    }
+
+    // Render the entry of the tree, the size goes after it with 'long', see 'tree_size'.
+    fn render_tree_entry(&self, file: &FileInfo, size: u64) -> String {
+        let entry = self.render_entry(file);
+        if !self.opts.long {
+            return entry;
+        }
+        format!("{} [{}]", entry, self.format_size(size))
+    }
+
+    // Render the file name to show, it is the colored name followed by its indicator.
+    pub fn render_name(&self, file: &FileInfo) -> String {
+        // The indicator is not colored or linked, so that it stays readable.
+        let mut name = format!("{}{}", self.render_bare_name(file), self.indicator(file));
+        if self.opts.mounts && file.is_mount {
+            name = format!("{} {}", name, self.paint(MOUNT_MARKER, Color::Cyan));
+        }
+        name
+    }
+
+    // Render the colored name without the indicator, it is linked to the file with 'hyperlink'.
+    fn render_bare_name(&self, file: &FileInfo) -> String {
+        let name = self.color_file_names(file);
+        if !self.opts.hyperlink {
+            return name;
+        }
+        format!(
+            "\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\",
+            file_url(&self.hostname, &file.path),
+            name
+        )
+    }
+
+    // Get the name to show, it is the full path of the file when 'full_path' is set
+    // and the directory being listed is known.
+    fn display_name<'f>(&self, file: &'f FileInfo) -> Cow<'f, str> {
+        match (self.opts.full_path, &self.display_dir) {
+            (Some(FullPath::Absolute), Some(_)) => file.path.to_string_lossy(),
+            (Some(FullPath::Relative), Some(dir)) => {
+                Cow::Owned(dir.join(&file.name).to_string_lossy().into_owned())
+            }
+            _ => Cow::Borrowed(&file.name),
+        }
+    }
+
+    // Get the indicator of the file type for the '-F' and '-p' options, just like GNU ls.
+    // '-p' only marks directories, and '-F' wins when both of them are given.
+    fn indicator(&self, file: &FileInfo) -> &'static str {
+        if !self.opts.classify {
+            if self.opts.slash_dirs && file.file_type == FileType::Dir {
+                return "/";
+            }
+            return "";
+        }
+
+        match file.file_type {
+            FileType::Dir => "/",
+            FileType::Link => "@",
+            FileType::Fifo => "|",
+            FileType::Socket => "=",
+            FileType::File if file.mode & 0o111 != 0 => "*",
+            FileType::File | FileType::CharDevice | FileType::BlockDevice => "",
+        }
+    }
+
+    // Get the indicator of the file that the symbolic link points to, it goes after the target in long mode.
+    // A broken link has nothing to indicate.
+    fn target_indicator(&self, file: &FileInfo) -> &'static str {
+        if !(self.opts.classify || self.opts.slash_dirs) || file.is_broken_link {
+            return "";
+        }
+        get_file_info_with_cache(&file.path, &mut NameCache::default(), true)
+            .map_or("", |target| self.indicator(&target))
+    }
+
+    // Color file name by file type when show file names.
+    // The colors set by 'LS_COLORS' go first, and the built-in colors are used for the rest.
+    //
+    // The built-in colors follow the default of GNU ls for the special kinds:
+    // executables are bold green, broken links are red, and directories writable by others are
+    // black on green with the sticky bit or blue on green without it, the sticky ones are white on blue.
+    //
+    // The name is quoted before it is colored, so the quotes are colored as well,
+    // and so is the icon before the name.
+    fn color_file_names(&self, file: &FileInfo) -> String {
+        let display_name = self.display_name(file);
+        let mut name = quote_name(&display_name, self.opts.quoting_style);
+        if self.opts.icons {
+            name = Cow::Owned(format!("{} {}", icon(file), name));
+        }
+
+        if self.opts.color {
+            if let Some(code) = self.opts.ls_colors.code(file) {
+                return format!("\x1b[{}m{}\x1b[0m", code, name);
+            }
+        }
+
+        self.paint_by_type(
+            &name,
+            &file.file_type,
+            file.mode,
+            file.is_broken_link,
+            category(file),
+        )
+        .to_string()
+    }
+
+    // Paint the text in the built-in color of the file type, the mode tells the executables
+    // and the special directories apart, and the other regular files are tinted by their categories.
+    fn paint_by_type(
+        &self,
+        text: &str,
+        file_type: &FileType,
+        mode: u32,
+        is_broken_link: bool,
+        category: Option<Category>,
+    ) -> ColoredString {
+        let other_writable = mode & 0o002 != 0;
+        let sticky = mode & 0o1000 != 0;
+        match file_type {
+            FileType::File if mode & 0o111 != 0 => self.paint_bold(text, Color::Green),
+            FileType::File => match category.map(Category::color) {
+                Some((color, true)) => self.paint_bold(text, color),
+                Some((color, false)) => self.paint(text, color),
+                None => self.paint(text, Color::White),
+            },
+            FileType::Dir if other_writable && sticky => {
+                self.paint_on(text, Color::Black, Color::Green)
+            }
+            FileType::Dir if other_writable => self.paint_on(text, Color::Blue, Color::Green),
+            FileType::Dir if sticky => self.paint_on(text, Color::White, Color::Blue),
+            FileType::Dir => self.paint(text, Color::Cyan),
+            FileType::Link if is_broken_link => self.paint(text, Color::Red),
+            FileType::Link => self.paint(text, Color::Blue),
+            // The special files get the colors of the defaults of dircolors.
+            FileType::CharDevice | FileType::BlockDevice => {
+                self.paint_bold_on(text, Color::Yellow, Color::Black)
+            }
+            FileType::Fifo => self.paint_on(text, Color::Yellow, Color::Black),
+            FileType::Socket => self.paint_bold(text, Color::Magenta),
+        }
+    }
+
+    // Color the git status like 'git status', the changes in the index are green, and the ones
+    // in the work tree are red, the clean and ignored files are grey.
+    fn paint_git_status(&self, [index, worktree]: [char; 2]) -> String {
+        let color_of = |c: char, changed: Color| match c {
+            '-' | '!' => Color::BrightBlack,
+            '?' => Color::Red,
+            _ => changed,
+        };
+        format!(
+            "{}{}",
+            self.paint(&index.to_string(), color_of(index, Color::Green)),
+            self.paint(&worktree.to_string(), color_of(worktree, Color::Red))
+        )
+    }
+
+    // All coloring goes through here, so the text stays plain when colors are turned off.
+    fn paint(&self, text: &str, color: Color) -> ColoredString {
+        if self.opts.color {
+            text.color(color)
+        } else {
+            text.normal()
+        }
+    }
+
+    // Paint the text in bold, it stays plain when colors are turned off.
+    fn paint_bold(&self, text: &str, color: Color) -> ColoredString {
+        if self.opts.color {
+            text.color(color).bold()
+        } else {
+            text.normal()
+        }
+    }
+
+    // Paint the text on the background, it stays plain when colors are turned off.
+    fn paint_on(&self, text: &str, color: Color, background: Color) -> ColoredString {
+        if self.opts.color {
+            text.color(color).on_color(background)
+        } else {
+            text.normal()
+        }
+    }
+
+    // Paint the text in bold on the background, it stays plain when colors are turned off.
+    fn paint_bold_on(&self, text: &str, color: Color, background: Color) -> ColoredString {
+        if self.opts.color {
+            text.color(color).on_color(background).bold()
+        } else {
+            text.normal()
+        }
+    }
+}
+
+// The units of the sizes counted in powers of 1024, and the ones in powers of 1000 for '--si'.
+pub const BINARY_UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+pub const SI_UNITS: [&str; 6] = ["B", "kB", "MB", "GB", "TB", "PB"];
+
+// Quote the file name in the style, just like GNU ls.
+// For example, 'a b' => a b by 'literal', 'a b' by 'shell', and "a b" by 'c'.
+pub fn quote_name(name: &str, style: QuotingStyle) -> Cow<'_, str> {
+    match style {
+        QuotingStyle::Literal => Cow::Borrowed(name),
+        QuotingStyle::C => Cow::Owned(format!("\"{}\"", c_escape(name, true))),
+        QuotingStyle::ShellEscape if name.chars().any(char::is_control) => {
+            Cow::Owned(shell_escape(name))
+        }
+        QuotingStyle::Shell | QuotingStyle::ShellEscape => shell_quote(name),
+    }
+}
+
+// The names made of these chars are safe for a shell without quotes.
+fn is_shell_safe(c: char) -> bool {
+    c.is_alphanumeric() || "-_./+,:@%^=".contains(c)
+}
+
+// Quote the name by single quotes when it needs to be, or by double quotes when it has a
+// single quote but nothing special inside double quotes.
+fn shell_quote(name: &str) -> Cow<'_, str> {
+    if !name.is_empty() && name.chars().all(is_shell_safe) {
+        return Cow::Borrowed(name);
+    }
+
+    if !name.contains('\'') {
+        Cow::Owned(format!("'{}'", name))
+    } else if !name.contains(['"', '$', '`', '\\', '!']) {
+        Cow::Owned(format!("\"{}\"", name))
+    } else {
+        Cow::Owned(format!("'{}'", name.replace('\'', "'\\''")))
+    }
+}
+
+// Quote the name by single quotes, and put the control chars out of them as $'\n',
+// which is understood by bash, zsh and ksh. For example, "a\nb" => 'a'$'\n''b'.
+fn shell_escape(name: &str) -> String {
+    let mut quoted = String::new();
+    let mut in_quotes = false;
+
+    for c in name.chars() {
+        if c.is_control() || c == '\'' {
+            if in_quotes {
+                quoted.push('\'');
+                in_quotes = false;
+            }
+            if c == '\'' {
+                quoted.push_str("\\'");
+            } else {
+                quoted.push_str(&format!("$'{}'", c_escape(&c.to_string(), false)));
+            }
+        } else {
+            if !in_quotes {
+                quoted.push('\'');
+                in_quotes = true;
+            }
+            quoted.push(c);
+        }
+    }
+    if in_quotes {
+        quoted.push('\'');
+    }
+
+    quoted
+}
+
+// Escape the control chars like C does, the other ones are written in octal, e.g. '\033'.
+// The double quotes are escaped when the text goes inside them.
+fn c_escape(text: &str, in_double_quotes: bool) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\x07' => escaped.push_str("\\a"),
+            '\x08' => escaped.push_str("\\b"),
+            '\x0b' => escaped.push_str("\\v"),
+            '\x0c' => escaped.push_str("\\f"),
+            '\\' => escaped.push_str("\\\\"),
+            '"' if in_double_quotes => escaped.push_str("\\\""),
+            c if c.is_control() => escaped.push_str(&format!("\\{:03o}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Get the 'file://' URL of the path for the links, e.g. 'file://host/tmp/a%20b'.
+// The path is made absolute with '/' as the separator, and the chars other than the unreserved
+// ones of RFC 3986 and ':' are percent-encoded, so the URL stays in one piece.
+fn file_url(hostname: &str, path: &Path) -> String {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let path = path
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/");
+
+    let mut url = format!("file://{}", hostname);
+    if !path.starts_with('/') {
+        url.push('/');
+    }
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/:".contains(&byte) {
+            url.push(byte as char);
+        } else {
+            url.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    url
+}
+
+// Get the name of this host.
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return String::new();
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+// Get the name of this host from the 'COMPUTERNAME' variable on Windows.
+#[cfg(windows)]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+// Turn file size to human readable size, it is divided by the base until it is less than the base.
+// The trailing zeros of the fraction are dropped.
+// For example: 1500 => '1.46KiB' by 1024 and BINARY_UNITS, '1.5kB' by 1000 and SI_UNITS
+pub fn human_readable_size(size: u64, base: u64, units: &[&str]) -> String {
+    let mut size = size as f64;
+    let mut unit = 0;
+
+    while size >= base as f64 && unit + 1 < units.len() {
+        size /= base as f64;
+        unit += 1;
+    }
+
+    // The rounding can carry the size up to the base, e.g. 1023.999KiB is shown as 1024.00KiB,
+    // then it's shown in the next unit instead.
+    if (size * 100.0).round() / 100.0 >= base as f64 && unit + 1 < units.len() {
+        size /= base as f64;
+        unit += 1;
+    }
+
+    let number = format!("{:.2}", size);
+    let number = number.trim_end_matches('0').trim_end_matches('.');
+    format!("{}{}", number, units[unit])
+}
+
+// Get the width of the widest cell of a column in chars.
+fn column_width(cells: &[String]) -> usize {
+    cells
+        .iter()
+        .map(|cell| cell.chars().count())
+        .max()
+        .unwrap_or(0)
+}
+
+// Put a ',' between every three digits of the number, e.g. '1,048,576'.
+pub fn group_thousands(number: u64) -> String {
+    let digits = number.to_string();
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+// Describe how long ago the time was from now, e.g. 'just now', '5 minutes ago', '2 years ago'.
+// A month is counted as 30 days and a year as 365 days, it is good enough for a rough phrase.
+// The time later than now is described as 'in 3 days'.
+pub fn relative_time(time: SystemTime, now: SystemTime) -> String {
+    let (secs, is_future) = match now.duration_since(time) {
+        Ok(elapsed) => (elapsed.as_secs(), false),
+        Err(err) => (err.duration().as_secs(), true),
+    };
+
+    if secs < 10 {
+        return "just now".to_string();
+    }
+
+    const UNITS: [(&str, u64); 7] = [
+        ("year", 365 * 24 * 60 * 60),
+        ("month", 30 * 24 * 60 * 60),
+        ("week", 7 * 24 * 60 * 60),
+        ("day", 24 * 60 * 60),
+        ("hour", 60 * 60),
+        ("minute", 60),
+        ("second", 1),
+    ];
+    let (unit, unit_secs) = UNITS
+        .iter()
+        .find(|(_, unit_secs)| secs >= *unit_secs)
+        .unwrap();
+
+    let count = secs / unit_secs;
+    let unit = if count == 1 {
+        unit.to_string()
+    } else {
+        format!("{}s", unit)
+    };
+
+    if is_future {
+        format!("in {} {}", count, unit)
+    } else {
+        format!("{} {} ago", count, unit)
+    }
+}
+
+// Get the width of the terminal, it returns None when the output is not a terminal.
+// If the width can't be got from the terminal, fall back to the 'COLUMNS' variable.
+#[cfg(unix)]
+pub fn terminal_width() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+        && size.ws_col > 0
+    {
+        return Some(size.ws_col as usize);
+    }
+
+    columns_from_env()
+}
+
+// Get the width of the terminal on Windows, it returns None when the output is not a terminal.
+// The console is not asked for its size, the 'COLUMNS' variable is used or 80 by default.
+#[cfg(windows)]
+pub fn terminal_width() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+
+    columns_from_env().or(Some(80))
+}
+
+// Get the width from the 'COLUMNS' variable.
+pub fn columns_from_env() -> Option<usize> {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .filter(|columns| *columns > 0)
+}
+
+// Count the chars of the string that take space on the terminal,
+// the ANSI escape sequences such as colors and links are skipped.
+pub fn visible_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&']') {
+            // Skip the OSC sequence until its terminator, e.g. '\x1b]8;;file:///tmp\x1b\\'.
+            while let Some(c) = chars.next() {
+                if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                    break;
+                }
+            }
+        } else if c == '\x1b' {
+            // Skip the escape sequence until its final byte, e.g. '\x1b[1;32m'.
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
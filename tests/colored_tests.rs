@@ -19,7 +19,7 @@ mod tests {
         println!("{}", "purple and magenta are the same".purple().magenta());
         println!("{}", "and so are normal and clear".normal().clear());
 
-        println!("{}", format!("{:30}", "format works as expected. This will be padded".blue()));
-        println!("{}", format!("{:.3}", "and this will be green but truncated to 3 chars".green()));
+        println!("{:30}", "format works as expected. This will be padded".blue());
+        println!("{:.3}", "and this will be green but truncated to 3 chars".green());
     }
 }
@@ -0,0 +1,516 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io;
+    #[cfg(unix)]
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    use std::time::{Duration, SystemTime};
+
+    use new_command::ls::{
+        analysis_mode, entry_paths, get_file_info, get_file_info_with_cache, group_thousands,
+        human_readable_size, icon, is_mount_point, iter_dir, list_dir, quote_name, relative_time,
+        BlockSize, Config, FileType, ListOptions, LsColors, NameCache, QuotingStyle, Renderer,
+        SortBy, BINARY_UNITS, SI_UNITS,
+    };
+
+    #[test]
+    fn test_list_dir_returns_sorted_file_infos() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("b.txt"), vec![0u8; 20]).unwrap();
+        fs::write(dir.path().join("a.txt"), vec![0u8; 10]).unwrap();
+        fs::write(dir.path().join(".hidden"), b"").unwrap();
+        fs::create_dir(dir.path().join("c")).unwrap();
+
+        let files = list_dir(dir.path(), &ListOptions::default()).unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["a.txt", "b.txt", "c"]);
+        assert_eq!(files[0].size, 10);
+        assert_eq!(files[0].file_type, FileType::File);
+        assert_eq!(files[2].file_type, FileType::Dir);
+
+        let opts = ListOptions {
+            all: true,
+            sort: SortBy::Size,
+            ..ListOptions::default()
+        };
+        let files = list_dir(dir.path(), &opts).unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names[names.len() - 3..], ["b.txt", "a.txt", ".hidden"]);
+        assert!(files.iter().any(|f| f.is_hidden));
+    }
+
+    #[test]
+    fn test_list_dir_of_file_returns_itself() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("single");
+        fs::write(&path, b"abc").unwrap();
+
+        let files = list_dir(&path, &ListOptions::default()).unwrap();
+        assert_eq!(files, [get_file_info(&path).unwrap()]);
+        assert_eq!(files[0].size, 3);
+    }
+
+    #[test]
+    fn test_get_file_info_of_missing_path_is_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing");
+
+        let err = get_file_info(&path).unwrap_err();
+        assert_eq!(err.io_error().kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().starts_with("cannot access '"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_file_info_of_broken_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broken");
+        symlink("missing", &path).unwrap();
+
+        // The link can't be followed, so its own metadata is used even when it is dereferenced.
+        for dereference in [false, true] {
+            let file =
+                get_file_info_with_cache(&path, &mut NameCache::default(), dereference).unwrap();
+            assert_eq!(file.file_type, FileType::Link);
+            assert!(file.is_broken_link);
+            assert_eq!(file.size, "missing".len() as u64);
+            assert_eq!(
+                file.link_target.as_deref(),
+                Some(std::path::Path::new("missing"))
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mount_point_is_on_another_device() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("file"), b"").unwrap();
+
+        // A directory is a mount point when its device differs from the one of its parent.
+        let sub = get_file_info(&dir.path().join("sub")).unwrap();
+        assert!(!is_mount_point(&sub, Some(sub.device)));
+        assert!(is_mount_point(&sub, Some(sub.device + 1)));
+        assert!(!is_mount_point(&sub, None));
+        let file = get_file_info(&dir.path().join("file")).unwrap();
+        assert!(!is_mount_point(&file, Some(file.device + 1)));
+
+        let files = list_dir(dir.path(), &ListOptions::default()).unwrap();
+        assert!(files.iter().all(|file| !file.is_mount));
+
+        // The directories under the root are mount points when they are on another device, like '/proc'.
+        let root_dev = fs::metadata("/").unwrap().dev();
+        let opts = ListOptions {
+            all: true,
+            ..ListOptions::default()
+        };
+        for file in list_dir(std::path::Path::new("/"), &opts).unwrap() {
+            let metadata = fs::symlink_metadata(&file.path).unwrap();
+            let expected = metadata.is_dir()
+                && metadata.dev() != root_dev
+                && file.name != "."
+                && file.name != "..";
+            assert_eq!(file.is_mount, expected, "{}", file.name);
+        }
+    }
+
+    #[test]
+    fn test_entry_paths_skip_failed_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = vec![
+            Ok(dir.path().join("a")),
+            Err(io::Error::other("flaky mount")),
+            Ok(dir.path().join("b")),
+        ];
+
+        assert_eq!(
+            entry_paths(dir.path(), entries),
+            [dir.path().join("a"), dir.path().join("b")]
+        );
+    }
+
+    #[test]
+    fn test_human_readable_size() {
+        assert_eq!(human_readable_size(1023, 1024, &BINARY_UNITS), "1023B");
+        assert_eq!(human_readable_size(1024, 1024, &BINARY_UNITS), "1KiB");
+        assert_eq!(human_readable_size(1025, 1024, &BINARY_UNITS), "1KiB");
+        assert_eq!(human_readable_size(1500, 1024, &BINARY_UNITS), "1.46KiB");
+        assert_eq!(human_readable_size(1 << 20, 1024, &BINARY_UNITS), "1MiB");
+        assert_eq!(human_readable_size(3 << 50, 1024, &BINARY_UNITS), "3PiB");
+
+        assert_eq!(human_readable_size(999, 1000, &SI_UNITS), "999B");
+        assert_eq!(human_readable_size(1500, 1000, &SI_UNITS), "1.5kB");
+        assert_eq!(human_readable_size(2_340_000, 1000, &SI_UNITS), "2.34MB");
+        assert_eq!(human_readable_size(999_999, 1000, &SI_UNITS), "1MB");
+    }
+
+    #[test]
+    fn test_group_thousands() {
+        assert_eq!(group_thousands(0), "0");
+        assert_eq!(group_thousands(999), "999");
+        assert_eq!(group_thousands(1000), "1,000");
+        assert_eq!(group_thousands(1_048_576), "1,048,576");
+        assert_eq!(group_thousands(u64::MAX), "18,446,744,073,709,551,615");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_analysis_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, b"").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let (permissions, file_type) = analysis_mode(&fs::metadata(&path).unwrap());
+        assert_eq!(permissions, "-rw-r-----");
+        assert_eq!(file_type, FileType::File);
+
+        let (permissions, file_type) = analysis_mode(&fs::metadata(dir.path()).unwrap());
+        assert!(permissions.starts_with('d'));
+        assert_eq!(file_type, FileType::Dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_analysis_mode_special_bits() {
+        let dir = tempfile::tempdir().unwrap();
+        let cases = [
+            (0o4755, "-rwsr-xr-x"),
+            (0o2755, "-rwxr-sr-x"),
+            (0o1777, "-rwxrwxrwt"),
+            (0o4644, "-rwSr--r--"),
+            (0o1644, "-rw-r--r-T"),
+        ];
+
+        for (mode, expected) in cases {
+            let path = dir.path().join(format!("{:o}", mode));
+            fs::write(&path, b"").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+
+            let (permissions, _) = analysis_mode(&fs::metadata(&path).unwrap());
+            assert_eq!(permissions, expected);
+        }
+    }
+
+    #[test]
+    fn test_list_dir_of_directory_itself() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("child"), b"").unwrap();
+
+        let opts = ListOptions {
+            directory: true,
+            ..ListOptions::default()
+        };
+        let files = list_dir(dir.path(), &opts).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].file_type, FileType::Dir);
+    }
+
+    #[test]
+    fn test_ls_colors() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        for name in ["a.tar", "B.TAR", "c.tar.gz", "plain"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let opts = ListOptions {
+            color: true,
+            ls_colors: LsColors::parse("di=01;34:*.tar=01;31:*.gz=35:*.TAR.GZ=33:broken"),
+            ..ListOptions::default()
+        };
+        let renderer = Renderer::new(&opts);
+        let render =
+            |name: &str| renderer.render_name(&get_file_info(&dir.path().join(name)).unwrap());
+
+        assert_eq!(render("sub"), "\x1b[01;34msub\x1b[0m");
+        assert_eq!(render("a.tar"), "\x1b[01;31ma.tar\x1b[0m");
+        // The extensions are matched regardless of case, and the longest one wins.
+        assert_eq!(render("B.TAR"), "\x1b[01;31mB.TAR\x1b[0m");
+        assert_eq!(render("c.tar.gz"), "\x1b[33mc.tar.gz\x1b[0m");
+
+        // 'fi' is not set, so the built-in color is used.
+        colored::control::set_override(true);
+        assert_eq!(render("plain"), "\x1b[37mplain\x1b[0m");
+    }
+
+    #[test]
+    fn test_quote_name() {
+        let quote = |name: &str, style: QuotingStyle| quote_name(name, style).into_owned();
+
+        assert_eq!(quote("a b", QuotingStyle::Literal), "a b");
+        assert_eq!(quote("a\nb", QuotingStyle::Literal), "a\nb");
+
+        assert_eq!(
+            quote("plain-name_1.txt", QuotingStyle::Shell),
+            "plain-name_1.txt"
+        );
+        assert_eq!(quote("a b", QuotingStyle::Shell), "'a b'");
+        assert_eq!(quote("$HOME", QuotingStyle::Shell), "'$HOME'");
+        assert_eq!(quote("it's", QuotingStyle::Shell), "\"it's\"");
+        assert_eq!(quote("it's $5", QuotingStyle::Shell), "'it'\\''s $5'");
+        assert_eq!(quote("", QuotingStyle::Shell), "''");
+
+        assert_eq!(quote("a b", QuotingStyle::ShellEscape), "'a b'");
+        assert_eq!(quote("a\nb", QuotingStyle::ShellEscape), "'a'$'\\n''b'");
+        assert_eq!(quote("\x1b'", QuotingStyle::ShellEscape), "$'\\033'\\'");
+
+        assert_eq!(quote("a b", QuotingStyle::C), "\"a b\"");
+        assert_eq!(
+            quote("a\nb\t\"c\"\\", QuotingStyle::C),
+            "\"a\\nb\\t\\\"c\\\"\\\\\""
+        );
+    }
+
+    #[test]
+    fn test_icons() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        for name in ["main.rs", "README.MD", "plain", "archive.tar.gz"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let icon_of = |name: &str| icon(&get_file_info(&dir.path().join(name)).unwrap());
+
+        assert_eq!(icon_of("sub"), '\u{f115}');
+        assert_eq!(icon_of("main.rs"), '\u{e7a8}');
+        // The extensions are matched regardless of case.
+        assert_eq!(icon_of("README.MD"), '\u{f48a}');
+        assert_eq!(icon_of("archive.tar.gz"), '\u{f410}');
+        assert_eq!(icon_of("plain"), '\u{f15b}');
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_allocated_size_of_sparse_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sparse");
+        fs::File::create(&path)
+            .unwrap()
+            .set_len(64 * 1024 * 1024)
+            .unwrap();
+
+        let file = get_file_info(&path).unwrap();
+        assert_eq!(file.size, 64 * 1024 * 1024);
+        assert!(file.allocated_size() < file.size);
+    }
+
+    #[test]
+    fn test_long_owner_name_not_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), b"").unwrap();
+        fs::write(dir.path().join("b"), b"").unwrap();
+
+        let mut files = list_dir(dir.path(), &ListOptions::default()).unwrap();
+        files[0].owner = "a_very_long_user_name_indeed".to_string();
+        files[1].owner = "bob".to_string();
+
+        let opts = ListOptions {
+            long: true,
+            ..ListOptions::default()
+        };
+        let mut out = Vec::new();
+        Renderer::new(&opts).show_infos(&files, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+
+        // The owners are padded to the longest one, so the columns after them are still aligned.
+        assert!(
+            lines[0].contains(" a_very_long_user_name_indeed "),
+            "{}",
+            out
+        );
+        assert!(
+            lines[1].contains(&format!(" bob{} ", " ".repeat(25))),
+            "{}",
+            out
+        );
+        assert_eq!(lines[0].len(), lines[1].len());
+    }
+
+    #[test]
+    fn test_config() {
+        let config = Config::parse(
+            r#"
+            long = true
+            sort = "size"
+            depth = 2
+
+            [colors]
+            directory = "bold magenta"
+            executable = "33"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.long, Some(true));
+        assert_eq!(config.human_readable, None);
+        assert_eq!(config.sort, Some(SortBy::Size));
+        assert_eq!(config.depth, Some(2));
+
+        // The colors of the config file win over 'LS_COLORS'.
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        let mut ls_colors = LsColors::parse("di=01;34:fi=36");
+        config.apply_colors(&mut ls_colors);
+        let opts = ListOptions {
+            color: true,
+            ls_colors,
+            ..ListOptions::default()
+        };
+        let renderer = Renderer::new(&opts);
+        let sub = get_file_info(&dir.path().join("sub")).unwrap();
+        assert_eq!(renderer.render_name(&sub), "\x1b[01;35msub\x1b[0m");
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert!(Config::parse("long = 1").is_err());
+        assert!(Config::parse("sort = \"random\"").is_err());
+        assert!(Config::parse("no_such_key = true").is_err());
+        assert!(Config::parse("[colors]\ndirectory = \"pink\"").is_err());
+        assert!(Config::parse("[colors]\nfolder = \"blue\"").is_err());
+    }
+
+    #[test]
+    fn test_list_large_dir_same_as_file_by_file() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..2000 {
+            fs::write(dir.path().join(format!("file{:04}", i)), vec![0u8; i % 7]).unwrap();
+        }
+
+        // The names of the owner and group come from the cache when listing the directory,
+        // they must be the same as the ones looked up for every single file.
+        let files = list_dir(dir.path(), &ListOptions::default()).unwrap();
+        assert_eq!(files.len(), 2000);
+        for file in &files {
+            assert_eq!(*file, get_file_info(&dir.path().join(&file.name)).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_parallel_listing_same_as_serial() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..3000 {
+            fs::write(dir.path().join(format!("f{}", i)), vec![0u8; i % 13]).unwrap();
+        }
+
+        // The ties of the sizes must be broken the same way as well.
+        for sort in [SortBy::Name, SortBy::Size] {
+            let serial = ListOptions {
+                sort,
+                ..ListOptions::default()
+            };
+            let parallel = ListOptions {
+                threads: 4,
+                ..serial.clone()
+            };
+            assert_eq!(
+                list_dir(dir.path(), &parallel).unwrap(),
+                list_dir(dir.path(), &serial).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_iter_dir_same_as_unsorted_listing() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..100 {
+            fs::write(dir.path().join(format!("f{}", i)), vec![0u8; i % 5]).unwrap();
+        }
+        fs::write(dir.path().join(".hidden"), b"").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+
+        // The files come in the order of the directory, just like the unsorted listing.
+        for all in [false, true] {
+            let opts = ListOptions {
+                all,
+                sort: SortBy::None,
+                ..ListOptions::default()
+            };
+            let streamed: Vec<_> = iter_dir(dir.path(), &opts).unwrap().collect();
+            assert_eq!(streamed, list_dir(dir.path(), &opts).unwrap());
+        }
+
+        let path = dir.path().join("f1");
+        let streamed: Vec<_> = iter_dir(&path, &ListOptions::default()).unwrap().collect();
+        assert_eq!(streamed, [get_file_info(&path).unwrap()]);
+    }
+
+    #[test]
+    fn test_relative_time() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let minute = 60;
+        let hour = 60 * minute;
+        let day = 24 * hour;
+        let cases = [
+            (0, "just now"),
+            (9, "just now"),
+            (10, "10 seconds ago"),
+            (minute, "1 minute ago"),
+            (2 * hour + 5 * minute, "2 hours ago"),
+            (day, "1 day ago"),
+            (3 * day, "3 days ago"),
+            (13 * day, "1 week ago"),
+            (45 * day, "1 month ago"),
+            (364 * day, "12 months ago"),
+            (800 * day, "2 years ago"),
+        ];
+
+        for (secs, expected) in cases {
+            let mtime = now - Duration::from_secs(secs);
+            assert_eq!(relative_time(mtime, now), expected, "{} seconds", secs);
+        }
+
+        assert_eq!(
+            relative_time(now + Duration::from_secs(3 * day), now),
+            "in 3 days"
+        );
+    }
+
+    #[test]
+    fn test_parse_block_size() {
+        let cases = [
+            ("512", BlockSize::Bytes(512)),
+            ("1K", BlockSize::Bytes(1024)),
+            ("K", BlockSize::Bytes(1024)),
+            ("1KiB", BlockSize::Bytes(1024)),
+            ("1KB", BlockSize::Bytes(1000)),
+            ("2M", BlockSize::Bytes(2 << 20)),
+            ("1GB", BlockSize::Bytes(1_000_000_000)),
+            ("human-readable", BlockSize::HumanReadable),
+            ("si", BlockSize::Si),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(s.parse::<BlockSize>(), Ok(expected), "{}", s);
+        }
+
+        for s in ["", "0", "1X", "K1", "-1", "99999999999E"] {
+            assert!(s.parse::<BlockSize>().is_err(), "{}", s);
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_list_dir_on_windows() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("file.txt"), vec![0u8; 10]).unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        let mut permissions = fs::metadata(dir.path().join("file.txt"))
+            .unwrap()
+            .permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(dir.path().join("file.txt"), permissions).unwrap();
+
+        let files = list_dir(dir.path(), &ListOptions::default()).unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["file.txt", "sub"]);
+        assert_eq!(files[0].size, 10);
+        assert_eq!(files[0].owner, "-");
+        assert_eq!(&files[0].permissions[..3], "-ar");
+        assert_eq!(files[1].file_type, FileType::Dir);
+        assert!(files[1].permissions.starts_with('d'));
+
+        let (_, file_type) = analysis_mode(&fs::metadata(dir.path()).unwrap());
+        assert_eq!(file_type, FileType::Dir);
+    }
+}
@@ -0,0 +1,2875 @@
+// The tests run on Unix only, because they need the pseudo terminal, fifos and sockets.
+#[cfg(all(test, unix))]
+mod tests {
+    use std::ffi::CString;
+    use std::fs;
+    use std::io::Read;
+    use std::os::fd::{FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+    use std::os::unix::net::UnixListener;
+    use std::path::Path;
+    use std::process::{Command, Output, Stdio};
+    use std::time::{Duration, SystemTime};
+
+    use tempfile::TempDir;
+
+    // Build a small fixture with a visible file, a hidden file and a sub directory.
+    fn fixture() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("visible.txt"), b"hello").unwrap();
+        fs::write(dir.path().join(".hidden"), b"secret").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub").join("inner.txt"), vec![0u8; 2048]).unwrap();
+        dir
+    }
+
+    // Run the nls binary with the given args against the path.
+    fn nls(args: &[&str], path: &Path) -> Output {
+        nls_with_env(args, path, &[])
+    }
+
+    // Run the nls binary with extra environment variables.
+    fn nls_with_env(args: &[&str], path: &Path, envs: &[(&str, &str)]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_nls"))
+            .args(args)
+            .arg(path)
+            .envs(envs.iter().copied())
+            .output()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_nls_options() {
+        let dir = fixture();
+        fs::write(dir.path().join("a b.txt"), b"").unwrap();
+
+        // A bare run picks up the default options.
+        let output = nls_with_env(&[], dir.path(), &[("NLS_OPTIONS", "-l")]);
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.starts_with("total "));
+        assert!(stdout.contains("visible.txt"));
+
+        // The flags on the command line override the default ones.
+        let output = nls_with_env(
+            &["--color=never"],
+            dir.path(),
+            &[("NLS_OPTIONS", "-l --color=always")],
+        );
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.starts_with("total "));
+        assert!(!stdout.contains('\x1b'));
+
+        // Quoted values keep their spaces.
+        let output = nls_with_env(&["-1"], dir.path(), &[("NLS_OPTIONS", "--hide 'a b.txt'")]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(stdout, "sub\nvisible.txt\n");
+
+        // Bad default options are reported like bad arguments.
+        let output = nls_with_env(&[], dir.path(), &[("NLS_OPTIONS", "--no-such-flag")]);
+        assert_eq!(output.status.code(), Some(2));
+    }
+
+    #[test]
+    fn test_config_file() {
+        let dir = fixture();
+        let home = tempfile::tempdir().unwrap();
+        let config_dir = home.path().join(".config").join("nls");
+        fs::create_dir_all(&config_dir).unwrap();
+        let envs = [
+            ("HOME", home.path().to_str().unwrap()),
+            ("XDG_CONFIG_HOME", ""),
+        ];
+
+        // A missing config file changes nothing.
+        let output = nls_with_env(&["-1"], dir.path(), &envs);
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "sub\nvisible.txt\n"
+        );
+
+        fs::write(
+            config_dir.join("config.toml"),
+            "long = true\nsort = \"size\"\n[colors]\ndirectory = \"01;35\"\n",
+        )
+        .unwrap();
+        let output = nls_with_env(&["--color=always"], dir.path(), &envs);
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.starts_with("total "));
+        assert!(stdout.contains("\x1b[01;35msub\x1b[0m"));
+        // The bigger directory goes first when sorted by size.
+        let lines: Vec<&str> = stdout.lines().skip(1).collect();
+        assert!(lines[0].contains("sub"), "{}", stdout);
+
+        // The command line wins over the config file.
+        let output = nls_with_env(&["--sort=name"], dir.path(), &envs);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let lines: Vec<&str> = stdout.lines().skip(1).collect();
+        assert!(lines[0].contains("sub") && lines[1].contains("visible.txt"));
+
+        // '--config' reads another file, and it must exist.
+        let other = home.path().join("other.toml");
+        fs::write(&other, "almost_all = true\n").unwrap();
+        let output = nls(&["-1", "--config", other.to_str().unwrap()], dir.path());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            ".hidden\nsub\nvisible.txt\n"
+        );
+        let missing = home.path().join("missing.toml");
+        let output = nls(&["--config", missing.to_str().unwrap()], dir.path());
+        assert_eq!(output.status.code(), Some(2));
+
+        // A broken config file is reported.
+        fs::write(config_dir.join("config.toml"), "long = \"yes\"\n").unwrap();
+        let output = nls_with_env(&[], dir.path(), &envs);
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("invalid config"));
+    }
+
+    #[test]
+    fn test_multiple_paths() {
+        let dir = fixture();
+        fs::create_dir(dir.path().join("other")).unwrap();
+        fs::write(dir.path().join("other").join("x.txt"), b"").unwrap();
+
+        let output = Command::new(env!("CARGO_BIN_EXE_nls"))
+            .args(["-1", "sub", "visible.txt", "other", "missing"])
+            .current_dir(dir.path())
+            .output()
+            .unwrap();
+        // The files go first, then the directories in order with their headers.
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "visible.txt\n\nother:\nx.txt\n\nsub:\ninner.txt\n"
+        );
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            "nls: cannot access 'missing': No such file or directory\n"
+        );
+        assert_eq!(output.status.code(), Some(2));
+
+        // A single directory has no header, and the files have no total.
+        let output = Command::new(env!("CARGO_BIN_EXE_nls"))
+            .args(["-l", "visible.txt", "sub"])
+            .current_dir(dir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines.len(), 5, "{}", stdout);
+        assert!(lines[0].ends_with(" visible.txt"));
+        assert_eq!(lines[1..3], ["", "sub:"]);
+        assert!(lines[3].starts_with("total "));
+        assert!(lines[4].ends_with(" inner.txt"));
+
+        let output = Command::new(env!("CARGO_BIN_EXE_nls"))
+            .args(["-1", "sub"])
+            .current_dir(dir.path())
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "inner.txt\n");
+    }
+
+    #[test]
+    fn test_full_path() {
+        let dir = fixture();
+        let run = |args: &[&str]| {
+            let output = Command::new(env!("CARGO_BIN_EXE_nls"))
+                .args(args)
+                .current_dir(dir.path())
+                .output()
+                .unwrap();
+            String::from_utf8(output.stdout).unwrap()
+        };
+        let root = dir.path().canonicalize().unwrap();
+
+        // The relative paths start with the directory as it was given.
+        assert_eq!(run(&["-1", "--full-path", "."]), "./sub\n./visible.txt\n");
+        assert_eq!(run(&["-1", "--full-path", "sub"]), "sub/inner.txt\n");
+        assert_eq!(run(&["-1", "--full-path", "visible.txt"]), "visible.txt\n");
+        assert!(run(&["-l", "--full-path", "sub"]).ends_with(" sub/inner.txt\n"));
+
+        // The absolute paths start with the canonical directory.
+        let inner = root.join("sub").join("inner.txt");
+        assert_eq!(
+            run(&["-1", "--absolute-path", "sub"]),
+            format!("{}\n", inner.display())
+        );
+        assert_eq!(
+            run(&["-1", "--absolute-path", "visible.txt"]),
+            format!("{}\n", root.join("visible.txt").display())
+        );
+        assert!(
+            run(&["-l", "--absolute-path", "sub"]).ends_with(&format!(" {}\n", inner.display()))
+        );
+
+        // The tree keeps the names.
+        assert_eq!(
+            run(&["--tree", "--full-path", "sub"]),
+            "sub\n└── inner.txt\n"
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let dir = fixture();
+        symlink("visible.txt", dir.path().join("link")).unwrap();
+        let size = |name: &str| fs::symlink_metadata(dir.path().join(name)).unwrap().len();
+        let footer = |args: &[&str]| {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            stdout.lines().last().unwrap().to_string()
+        };
+
+        // The hidden files are only counted when they are shown.
+        let total = size("sub") + size("visible.txt") + size("link");
+        assert_eq!(
+            footer(&["--summary"]),
+            format!("1 directory, 1 file, 1 symlink, {} total", total)
+        );
+        let total = total + size(".hidden");
+        assert_eq!(
+            footer(&["-A", "--summary"]),
+            format!("1 directory, 2 files, 1 symlink, {} total", total)
+        );
+
+        // The tree counts everything under the root.
+        let total = size("sub") + size("visible.txt") + size("link") + 2048;
+        assert_eq!(
+            footer(&["--tree", "--summary"]),
+            format!("1 directory, 2 files, 1 symlink, {} total", total)
+        );
+
+        // The total size is formatted just like the details.
+        assert!(footer(&["-h", "--summary"]).ends_with("iB total"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let dir = fixture();
+        let script = dir.path().join("run.sh");
+        fs::write(&script, b"#!/bin/sh\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        symlink("sub", dir.path().join("link")).unwrap();
+
+        let names = |args: &[&str]| String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+        assert_eq!(names(&["--filter=dir"]), "sub\n");
+        assert_eq!(names(&["--filter=file"]), "run.sh\nvisible.txt\n");
+        assert_eq!(names(&["--filter=exec"]), "run.sh\n");
+        // The types are united, and they work with '-a' and the patterns.
+        assert_eq!(names(&["--filter=dir,link"]), "link\nsub\n");
+        assert_eq!(
+            names(&["--filter", "dir", "--filter", "link"]),
+            "link\nsub\n"
+        );
+        assert_eq!(
+            names(&["-A", "--filter=file"]),
+            ".hidden\nrun.sh\nvisible.txt\n"
+        );
+        assert_eq!(names(&["--filter=file", "-I", "*.sh"]), "visible.txt\n");
+    }
+
+    #[test]
+    fn test_grep() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["main.rs", "lib.rs", "README.md", "notes.txt"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let names = |args: &[&str]| String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+        assert_eq!(names(&["--grep", r"\.rs$"]), "lib.rs\nmain.rs\n");
+        assert_eq!(names(&["--grep=^[A-Z]"]), "README.md\n");
+        assert_eq!(
+            names(&["--grep", r"\.rs$", "--grep-invert"]),
+            "README.md\nnotes.txt\n"
+        );
+
+        // An invalid pattern is refused by the parser.
+        let output = nls(&["--grep=("], dir.path());
+        assert_eq!(output.status.code(), Some(2));
+    }
+
+    #[test]
+    fn test_legend() {
+        let dir = fixture();
+        let stdout = |args: &[&str]| String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+
+        // The types are painted just like the names, so the key follows the listing.
+        let colored = stdout(&["--legend", "--color=always"]);
+        let legend = colored.lines().last().unwrap();
+        assert!(legend.starts_with("legend: "), "{}", colored);
+        assert!(legend.contains("\x1b[36mdirectory\x1b[0m"));
+        assert!(legend.contains("\x1b[1;32mexecutable\x1b[0m"));
+        assert!(legend.contains("\x1b[31mbroken symlink\x1b[0m"));
+        assert!(colored.contains("\x1b[36msub\x1b[0m"));
+
+        // There is nothing to explain without colors.
+        assert!(!stdout(&["--legend", "--color=never"]).contains("legend"));
+        assert!(!stdout(&["--color=always"]).contains("legend"));
+    }
+
+    #[test]
+    fn test_glob_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.txt", "a.txt", ".c.txt", "d.md", "[x].txt"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub").join("e.txt"), b"").unwrap();
+        let run = |args: &[&str]| {
+            Command::new(env!("CARGO_BIN_EXE_nls"))
+                .args(args)
+                .current_dir(dir.path())
+                .output()
+                .unwrap()
+        };
+
+        // The hidden files are not matched, just like a shell.
+        let output = run(&["-1", "*.txt"]);
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "[x].txt\na.txt\nb.txt\n"
+        );
+
+        let output = run(&["-1", "?.*", "*/"]);
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "a.txt\nb.txt\nd.md\n\nsub:\ne.txt\n"
+        );
+
+        // An existing path is never expanded.
+        let output = run(&["-1", "[x].txt"]);
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "[x].txt\n");
+
+        let output = run(&["-1", "*.rs"]);
+        assert_eq!(output.status.code(), Some(2));
+        assert!(output.stdout.is_empty());
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            "nls: *.rs: no matches found\n"
+        );
+    }
+
+    #[test]
+    fn test_expand_home_and_variables() {
+        let dir = fixture();
+        let run = |args: &[&str]| {
+            Command::new(env!("CARGO_BIN_EXE_nls"))
+                .args(args)
+                .env("HOME", dir.path())
+                .env("NLS_TEST_DIR", "sub")
+                .env_remove("NLS_TEST_UNSET")
+                .current_dir(dir.path())
+                .output()
+                .unwrap()
+        };
+
+        let output = run(&["-1", "~"]);
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "sub\nvisible.txt\n"
+        );
+
+        let output = run(&["-1", "~/sub"]);
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "inner.txt\n");
+
+        for arg in [
+            "$NLS_TEST_DIR",
+            "${NLS_TEST_DIR}/inner.txt",
+            "~/$NLS_TEST_DIR",
+        ] {
+            let output = run(&["-1", arg]);
+            assert!(output.status.success(), "{}", arg);
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), "inner.txt\n");
+        }
+
+        // The unknown users and variables are reported instead of a panic.
+        let output = run(&["-1", "~no_such_user_of_nls/x"]);
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("no such user 'no_such_user_of_nls'"));
+
+        let output = run(&["-1", "$NLS_TEST_UNSET/x"]);
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("the variable 'NLS_TEST_UNSET' is not set"));
+    }
+
+    #[test]
+    fn test_quoting_style() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a b"), b"").unwrap();
+        fs::write(dir.path().join("new\nline"), b"").unwrap();
+        symlink("a b", dir.path().join("link")).unwrap();
+
+        let output = nls(&["-1"], dir.path());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "a b\nlink\nnew\nline\n"
+        );
+
+        let output = nls(&["-1", "--quoting-style=shell"], dir.path());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "'a b'\nlink\n'new\nline'\n"
+        );
+
+        let output = nls(&["-1", "--quoting-style=c"], dir.path());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "\"a b\"\n\"link\"\n\"new\\nline\"\n"
+        );
+
+        // The targets of links are quoted as well.
+        let output = nls(&["-l", "--quoting-style=shell-escape"], dir.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains(" link -> 'a b'\n"));
+        assert!(stdout.contains(" 'new'$'\\n''line'\n"));
+    }
+
+    #[test]
+    fn test_commas() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a1", "a2", "a3", "a4", "a5", "a6", ".h"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let output = nls(&["-m"], dir.path());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "a1, a2, a3, a4, a5, a6\n"
+        );
+
+        let output = nls(&["-m", "-A"], dir.path());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            ".h, a1, a2, a3, a4, a5, a6\n"
+        );
+
+        // The lines are wrapped at the width of the terminal, with the comma left at the end.
+        let stdout = nls_on_tty(&["-m", "--color=never"], dir.path(), 12);
+        assert_eq!(stdout, "a1, a2, a3,\na4, a5, a6\n");
+
+        // The commas are not colored.
+        let stdout = nls_on_tty(&["-m", "--color=always"], dir.path(), 80);
+        assert_eq!(strip_escapes(&stdout), "a1, a2, a3, a4, a5, a6\n");
+        assert!(stdout.contains("\x1b[0m, "));
+
+        // The last one of '-m' and '-1' wins.
+        let output = nls(&["-m", "-1"], dir.path());
+        assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 6);
+    }
+
+    #[test]
+    fn test_hyperlink() {
+        let dir = fixture();
+        let real_dir = dir.path().canonicalize().unwrap();
+        fs::write(real_dir.join("a b"), b"").unwrap();
+
+        let output = nls(&["-1", "--hyperlink=always"], &real_dir);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let link = |name: &str, url_name: &str| {
+            format!(
+                "{}/{}\x1b\\{}\x1b]8;;\x1b\\\n",
+                real_dir.display(),
+                url_name,
+                name
+            )
+        };
+        for line in stdout.lines() {
+            assert!(line.starts_with("\x1b]8;;file://"), "{:?}", line);
+        }
+        assert!(stdout.contains(&link("visible.txt", "visible.txt")));
+        assert!(stdout.contains(&link("a b", "a%20b")));
+
+        // The links take no space in the grid.
+        let stdout = nls_on_tty(&["--hyperlink=always", "--color=never"], &real_dir, 80);
+        assert_eq!(
+            strip_escapes(&stdout),
+            nls_on_tty(&["--color=never"], &real_dir, 80)
+        );
+
+        // 'auto' only links on a terminal, and never when colors are turned off.
+        let output = nls(&["-1", "--hyperlink=auto"], &real_dir);
+        assert!(!String::from_utf8(output.stdout).unwrap().contains("\x1b]8"));
+        let stdout = nls_on_tty(&["--hyperlink=auto"], &real_dir, 80);
+        assert!(stdout.contains("\x1b]8;;file://"));
+        let stdout = nls_on_tty(&["--hyperlink=auto", "--color=never"], &real_dir, 80);
+        assert!(!stdout.contains("\x1b]8"));
+    }
+
+    #[test]
+    fn test_icons() {
+        let dir = fixture();
+        fs::write(dir.path().join("main.rs"), b"").unwrap();
+
+        let output = nls(&["-1", "--icons=always"], dir.path());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "\u{e7a8} main.rs\n\u{f115} sub\n\u{f15c} visible.txt\n"
+        );
+
+        // The icon is colored like the name.
+        let output = nls(&["-1", "--icons=always", "--color=always"], dir.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            stdout.contains("\x1b[36m\u{f115} sub\x1b[0m"),
+            "{:?}",
+            stdout
+        );
+
+        let output = nls(&["-T", "--icons=always"], dir.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("\u{f115} sub\n"));
+        assert!(stdout.contains("\u{f15c} inner.txt\n"));
+
+        // 'auto' only shows them on a terminal.
+        let output = nls(&["-1", "--icons=auto"], dir.path());
+        assert!(!String::from_utf8(output.stdout)
+            .unwrap()
+            .contains('\u{f115}'));
+        let stdout = nls_on_tty(&["--icons=auto"], dir.path(), 80);
+        assert!(stdout.contains('\u{f115}'));
+    }
+
+    #[test]
+    fn test_git_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join("clean.txt"), b"clean").unwrap();
+        fs::write(dir.path().join("modified.txt"), b"old").unwrap();
+        fs::write(dir.path().join(".gitignore"), b"*.log\n").unwrap();
+
+        // Commit the first files.
+        let mut index = repo.index().unwrap();
+        for name in ["clean.txt", "modified.txt", ".gitignore"] {
+            index.add_path(Path::new(name)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("nls", "nls@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+
+        fs::write(dir.path().join("modified.txt"), b"new").unwrap();
+        fs::write(dir.path().join("staged.txt"), b"").unwrap();
+        index.add_path(Path::new("staged.txt")).unwrap();
+        index.write().unwrap();
+        fs::write(dir.path().join("untracked.txt"), b"").unwrap();
+        fs::write(dir.path().join("debug.log"), b"").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub").join("new.txt"), b"").unwrap();
+
+        let output = nls(&["-l", "--git"], dir.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        for row in [
+            " -- clean.txt",
+            " -M modified.txt",
+            " A- staged.txt",
+            " ?? untracked.txt",
+            " !! debug.log",
+            " ?? sub",
+        ] {
+            assert!(
+                stdout.contains(&format!("{}\n", row)),
+                "{} in:\n{}",
+                row,
+                stdout
+            );
+        }
+
+        // The files out of any work tree get a blank status.
+        let other = fixture();
+        let output = nls(&["-l", "--git"], other.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("    visible.txt\n"), "{}", stdout);
+
+        // The column is only in the details.
+        let output = nls(&["-1", "--git"], dir.path());
+        assert!(!String::from_utf8(output.stdout).unwrap().contains("??"));
+    }
+
+    #[test]
+    fn test_security_context() {
+        if !xattr::SUPPORTED_PLATFORM {
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, b"").unwrap();
+
+        // The file gets a context on SELinux systems, it has none anywhere else.
+        let expected = match xattr::get(&path, "security.selinux").ok().flatten() {
+            Some(value) => String::from_utf8(value)
+                .unwrap()
+                .trim_end_matches('\0')
+                .to_string(),
+            None => "?".to_string(),
+        };
+
+        let output = nls(&["-lZ"], &path);
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let columns: Vec<&str> = stdout.split_whitespace().collect();
+        assert_eq!(columns[4], expected);
+
+        // The column is only there with '-Z'.
+        let output = nls(&["-l"], &path);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_ne!(stdout.split_whitespace().nth(4), Some(expected.as_str()));
+    }
+
+    #[test]
+    fn test_xattr_marker() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("plain"), b"").unwrap();
+        fs::write(dir.path().join("tagged"), b"").unwrap();
+        // Some filesystems don't support the user attributes, there is nothing to show then.
+        if xattr::set(dir.path().join("tagged"), "user.nls", b"1").is_err() {
+            return;
+        }
+
+        let output = nls(&["-l"], dir.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let permissions = |name: &str| {
+            stdout
+                .lines()
+                .find(|line| line.ends_with(name))
+                .and_then(|line| line.split_whitespace().next())
+                .unwrap()
+                .to_string()
+        };
+        assert!(permissions("tagged").ends_with('@'));
+        assert_eq!(permissions("plain").len(), 10);
+
+        // The rows are still aligned.
+        let links: Vec<usize> = stdout
+            .lines()
+            .skip(1)
+            .map(|line| line.find(" 1 ").unwrap())
+            .collect();
+        assert_eq!(links[0], links[1]);
+    }
+
+    #[test]
+    fn test_git_ignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join(".gitignore"), b"*.log\nbuild/\n").unwrap();
+        for name in ["a.log", "keep.txt", "tracked.log"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        fs::create_dir(dir.path().join("build")).unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub").join("b.log"), b"").unwrap();
+        fs::write(dir.path().join("sub").join("c.txt"), b"").unwrap();
+        fs::write(dir.path().join("sub").join(".gitignore"), b"c.txt\n").unwrap();
+
+        // A file added to git is never ignored.
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("tracked.log")).unwrap();
+        index.write().unwrap();
+
+        let output = nls(&["-1", "--git-ignore"], dir.path());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "keep.txt\nsub\ntracked.log\n"
+        );
+
+        // '-a' doesn't bring the ignored files back.
+        let output = nls(&["-1", "-A", "--git-ignore"], dir.path());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            ".gitignore\nkeep.txt\nsub\ntracked.log\n"
+        );
+
+        // The nested '.gitignore' files count in the tree.
+        let output = nls(&["-T", "--git-ignore"], dir.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("sub\n"));
+        assert!(!stdout.contains("b.log"));
+        assert!(!stdout.contains("c.txt"));
+
+        let output = nls(&["-1"], dir.path());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "a.log\nbuild\nkeep.txt\nsub\ntracked.log\n"
+        );
+    }
+
+    #[test]
+    fn test_total_size() {
+        let dir = nested_fixture();
+        fs::write(dir.path().join("sub").join("big.txt"), vec![0u8; 1000]).unwrap();
+        symlink("/", dir.path().join("sub").join("root")).unwrap();
+
+        // The size of 'sub' is its content: 'b.txt', 'big.txt', 'deep/c.txt' and the link itself.
+        let output = nls(&["-l", "--total-size"], dir.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(long_row(&stdout, " sub")[4], (1 + 1000 + 1 + 1).to_string());
+        assert_eq!(long_row(&stdout, " a.txt")[4], "1");
+
+        // '-S' sorts on the total sizes.
+        let output = nls(&["-1", "-S", "--total-size"], dir.path());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "sub\na.txt\n");
+
+        // The directory itself is summed up by '-d'.
+        let output = nls(&["-l", "-d", "--total-size"], &dir.path().join("sub"));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(long_row(&stdout, "sub")[4], "1003");
+
+        let output = nls(&["-l"], dir.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_ne!(long_row(&stdout, " sub")[4], "1003");
+    }
+
+    #[test]
+    fn test_allocated_size() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::File::create(dir.path().join("sparse"))
+            .unwrap()
+            .set_len(64 * 1024 * 1024)
+            .unwrap();
+        fs::write(dir.path().join("small"), b"x").unwrap();
+        let allocated = |name: &str| fs::metadata(dir.path().join(name)).unwrap().blocks() * 512;
+
+        let output = nls(&["-l"], dir.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(
+            long_row(&stdout, "sparse")[4],
+            (64 * 1024 * 1024).to_string()
+        );
+        assert_eq!(long_row(&stdout, "small")[4], "1");
+
+        let output = nls(&["-l", "--allocated-size"], dir.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(
+            long_row(&stdout, "sparse")[4],
+            allocated("sparse").to_string()
+        );
+        assert_eq!(
+            long_row(&stdout, "small")[4],
+            allocated("small").to_string()
+        );
+        assert!(allocated("sparse") < 64 * 1024 * 1024);
+
+        // The last one of '--allocated-size' and '--apparent-size' wins.
+        let output = nls(&["-l", "--allocated-size", "--apparent-size"], dir.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(long_row(&stdout, "small")[4], "1");
+    }
+
+    #[test]
+    fn test_verbose() {
+        let dir = fixture();
+
+        // Nothing but the listing goes to stdout.
+        for args in [&[][..], &["-l"], &["-T"], &["--verbose"]] {
+            let output = nls(args, dir.path());
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            assert!(!stdout.contains("status:"), "{:?}: {}", args, stdout);
+        }
+
+        let output = nls(&["-1"], dir.path());
+        assert!(output.stderr.is_empty());
+
+        let output = nls(&["-1", "-l", "--verbose"], dir.path());
+        assert!(output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("nls: status: 1\n"), "{}", stderr);
+        assert!(stderr.contains("nls: done in "));
+    }
+
+    #[test]
+    fn test_generate_completions() {
+        let output = nls(&["--generate-completions", "bash"], Path::new("."));
+        assert!(output.status.success());
+        let script = String::from_utf8(output.stdout).unwrap();
+        assert!(script.contains("_nls()"));
+        assert!(script.contains("--tree"));
+        assert!(script.contains("--human-readable"));
+
+        for shell in ["zsh", "fish", "powershell", "elvish"] {
+            let output = nls(&["--generate-completions", shell], Path::new("."));
+            assert!(output.status.success(), "{}", shell);
+            assert!(!output.stdout.is_empty(), "{}", shell);
+        }
+
+        assert!(!nls(&["--generate-completions", "tcsh"], Path::new("."))
+            .status
+            .success());
+    }
+
+    #[test]
+    fn test_generate_man() {
+        let output = nls(&["--generate-man"], Path::new("."));
+        assert!(output.status.success());
+        let page = String::from_utf8(output.stdout).unwrap();
+        assert!(page.starts_with(".ie"));
+        assert!(page.contains(".TH nls 1"));
+        assert!(page.contains("\\fB\\-\\-tree\\fR"));
+        assert!(page.contains("human\\-readable"));
+        assert!(page.contains("Tianyi"));
+    }
+
+    #[test]
+    fn test_help_is_long_only() {
+        let output = nls(&["--help"], Path::new("."));
+        assert!(output.status.success());
+        let help = String::from_utf8(output.stdout).unwrap();
+        assert!(help.contains("-h, --human-readable"));
+        assert!(help.contains("-H, --dereference-command-line"));
+
+        // '-h' lists the files with human readable sizes instead of printing the help.
+        let dir = fixture();
+        let stdout = String::from_utf8(nls(&["-l", "-h"], dir.path()).stdout).unwrap();
+        assert!(stdout.contains(" 5B "));
+    }
+
+    #[test]
+    fn test_every_status_path_runs_without_panic() {
+        let dir = fixture();
+
+        // One set of args for each status of the state machine.
+        let cases: [&[&str]; 8] = [
+            &[],
+            &["-l"],
+            &["-a"],
+            &["-a", "-l"],
+            &["-h"],
+            &["-l", "-h"],
+            &["-a", "-l", "-h"],
+            &["-T"],
+        ];
+
+        for args in cases {
+            let output = nls(args, dir.path());
+            assert!(
+                output.status.success(),
+                "nls {:?} failed: {}",
+                args,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+    }
+
+    #[test]
+    fn test_hidden_files_only_shown_with_all() {
+        let dir = fixture();
+
+        let stdout = String::from_utf8(nls(&[], dir.path()).stdout).unwrap();
+        assert!(stdout.contains("visible.txt"));
+        assert!(!stdout.contains(".hidden"));
+
+        let stdout = String::from_utf8(nls(&["-a"], dir.path()).stdout).unwrap();
+        assert!(stdout.contains(".hidden"));
+    }
+
+    #[test]
+    fn test_dot_entries_only_shown_with_all() {
+        let dir = fixture();
+
+        // '.' and '..' come first, because '.' sorts before any other name.
+        let stdout = String::from_utf8(nls(&["-a", "-1"], dir.path()).stdout).unwrap();
+        let names: Vec<&str> = stdout.lines().collect();
+        assert_eq!(names, [".", "..", ".hidden", "sub", "visible.txt"]);
+
+        let stdout = String::from_utf8(nls(&["-A", "-1"], dir.path()).stdout).unwrap();
+        let names: Vec<&str> = stdout.lines().collect();
+        assert_eq!(names, [".hidden", "sub", "visible.txt"]);
+
+        // The last one of '-a' and '-A' wins.
+        let stdout = String::from_utf8(nls(&["-a", "-A", "-1"], dir.path()).stdout).unwrap();
+        assert!(!stdout.lines().any(|name| name == "."));
+
+        let stdout = String::from_utf8(nls(&["-a", "-l"], dir.path()).stdout).unwrap();
+        assert!(long_row(&stdout, "..")[0].starts_with('d'));
+    }
+
+    #[test]
+    fn test_ignore_and_hide_patterns() {
+        let dir = fixture();
+        fs::write(dir.path().join("app.log"), b"").unwrap();
+        fs::create_dir(dir.path().join("node_modules")).unwrap();
+
+        let names = |args: &[&str]| -> Vec<String> {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            stdout.lines().map(|line| line.to_string()).collect()
+        };
+
+        // Patterns are accumulated when the option is given many times.
+        let args = ["-I", "*.log", "--ignore", "node_modules"];
+        assert_eq!(names(&args), ["sub", "visible.txt"]);
+        assert_eq!(
+            names(&["--hide", "*.log", "--hide=node_modules"]),
+            ["sub", "visible.txt"]
+        );
+
+        // '-A' overrides '--hide', but never '--ignore'.
+        assert!(!names(&["-A", "--ignore", "*.log"]).contains(&"app.log".to_string()));
+        assert!(names(&["-A", "--hide", "*.log"]).contains(&"app.log".to_string()));
+
+        // '*' doesn't match a leading '.'.
+        assert!(names(&["-A", "-I", "*"]).contains(&".hidden".to_string()));
+        assert_eq!(names(&["-A", "-I", "*", "-I", ".*"]), Vec::<String>::new());
+
+        let output = nls(&["--ignore", "[z"], dir.path());
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn test_long_listing_composes_with_human_readable() {
+        let dir = fixture();
+        let path = dir.path().join("sub");
+
+        let stdout = String::from_utf8(nls(&["-l"], &path).stdout).unwrap();
+        assert!(stdout.contains(" 2048 "));
+
+        let stdout = String::from_utf8(nls(&["-l", "-h"], &path).stdout).unwrap();
+        assert!(stdout.contains("2KiB"));
+
+        let stdout = String::from_utf8(nls(&["-l", "--si"], &path).stdout).unwrap();
+        assert!(stdout.contains("2.05kB"));
+    }
+
+    // Run the nls binary on a pseudo terminal of the given width, and return what it printed.
+    fn nls_on_tty(args: &[&str], path: &Path, width: u16) -> String {
+        nls_on_tty_with_env(args, path, width, &[])
+    }
+
+    // Run the nls binary on a pseudo terminal with extra environment variables.
+    fn nls_on_tty_with_env(
+        args: &[&str],
+        path: &Path,
+        width: u16,
+        envs: &[(&str, &str)],
+    ) -> String {
+        let (mut master, mut slave) = (0, 0);
+        let size = libc::winsize {
+            ws_row: 24,
+            ws_col: width,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let rc = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                &size,
+            )
+        };
+        assert_eq!(rc, 0);
+        let mut master = unsafe { fs::File::from_raw_fd(master) };
+        let slave = unsafe { OwnedFd::from_raw_fd(slave) };
+
+        let status = Command::new(env!("CARGO_BIN_EXE_nls"))
+            .args(args)
+            .arg(path)
+            .envs(envs.iter().copied())
+            .stdout(Stdio::from(slave))
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        // Reading the master returns an error once the output is drained and the slave is closed.
+        let mut output = Vec::new();
+        let _ = master.read_to_end(&mut output);
+
+        // The terminal turns '\n' into '\r\n'.
+        String::from_utf8(output).unwrap().replace("\r\n", "\n")
+    }
+
+    #[test]
+    fn test_empty_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".hidden"), b"").unwrap();
+
+        // Nothing is printed for the scripts, not even a blank line.
+        for args in [&[][..], &["-w", "80"], &["-m"], &["-x"]] {
+            let output = nls(args, dir.path());
+            assert!(output.status.success());
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+        }
+        let stdout = String::from_utf8(nls(&["-R"], dir.path()).stdout).unwrap();
+        assert_eq!(stdout, format!("{}:\n", dir.path().display()));
+        let stdout = String::from_utf8(nls(&["-l"], dir.path()).stdout).unwrap();
+        assert_eq!(stdout, "total 0\n");
+
+        // The terminal gets a hint, the tree gets it as the only branch.
+        let hint = |args: &[&str]| nls_on_tty(args, dir.path(), 80);
+        assert_eq!(hint(&["--color=never"]), "(empty)\n");
+        assert_eq!(hint(&["--color=never", "-l"]), "total 0\n(empty)\n");
+        assert!(hint(&["--color=never", "--tree"]).ends_with("\n└── (empty)\n"));
+        // The hidden file is listed with '-a', so there is no hint.
+        assert_eq!(hint(&["--color=never", "-A"]), ".hidden\n");
+    }
+
+    // Find the long listing row of the named entry and split it into columns.
+    fn long_row(stdout: &str, name: &str) -> Vec<String> {
+        stdout
+            .lines()
+            .find(|line| line.ends_with(name))
+            .unwrap_or_else(|| panic!("{} is not listed in:\n{}", name, stdout))
+            .split_whitespace()
+            .map(|col| col.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_group_name_resolved_for_files_and_fifos() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("regular"), b"data").unwrap();
+        let fifo = CString::new(dir.path().join("pipe").as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+
+        let group = users::get_group_by_gid(users::get_current_gid())
+            .map(|g| g.name().to_string_lossy().into_owned())
+            .unwrap();
+
+        let stdout = String::from_utf8(nls(&["-l"], dir.path()).stdout).unwrap();
+
+        // The regular file goes through the users crate, the fifo through libc,
+        // and both should agree on the group name.
+        let regular = long_row(&stdout, "regular");
+        assert!(regular[0].starts_with('-'));
+        assert_eq!(regular[3], group);
+
+        let pipe = long_row(&stdout, "pipe");
+        assert!(pipe[0].starts_with('p'));
+        assert_eq!(pipe[3], group);
+    }
+
+    #[test]
+    fn test_numeric_uid_gid() {
+        let dir = fixture();
+        let metadata = fs::symlink_metadata(dir.path().join("visible.txt")).unwrap();
+
+        // '-n' implies '-l'.
+        for args in [&["-n"][..], &["-l", "--numeric-uid-gid"]] {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            let row = long_row(&stdout, "visible.txt");
+            assert_eq!(row[2], metadata.uid().to_string());
+            assert_eq!(row[3], metadata.gid().to_string());
+        }
+    }
+
+    #[test]
+    fn test_octal_permissions() {
+        let dir = fixture();
+        let path = dir.path().join("visible.txt");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let stdout =
+            String::from_utf8(nls(&["-l", "--octal-permissions"], dir.path()).stdout).unwrap();
+        let row = long_row(&stdout, "visible.txt");
+        assert_eq!(&row[..2], ["0640", "-rw-r-----"]);
+
+        // It is only a part of the details.
+        let stdout = String::from_utf8(nls(&["--octal-permissions"], dir.path()).stdout).unwrap();
+        assert_eq!(stdout, "sub\nvisible.txt\n");
+    }
+
+    #[test]
+    fn test_author() {
+        let dir = fixture();
+        let full = String::from_utf8(nls(&["-l"], dir.path()).stdout).unwrap();
+        let full = long_row(&full, "visible.txt");
+
+        // '--author' implies '-l', and the author follows the link count.
+        let stdout = String::from_utf8(nls(&["--author"], dir.path()).stdout).unwrap();
+        let row = long_row(&stdout, "visible.txt");
+        assert_eq!(row.len(), full.len() + 1);
+        assert_eq!(row[2], full[2]);
+        assert_eq!(&row[3..], &full[2..]);
+    }
+
+    #[test]
+    fn test_owner_and_group_columns_omitted() {
+        let dir = fixture();
+        let full = String::from_utf8(nls(&["-l"], dir.path()).stdout).unwrap();
+        let full = long_row(&full, "visible.txt");
+        let (owner, group) = (&full[2], &full[3]);
+
+        // '-o' and '-g' imply '-l', and the size follows the remaining column.
+        let stdout = String::from_utf8(nls(&["-o"], dir.path()).stdout).unwrap();
+        let row = long_row(&stdout, "visible.txt");
+        assert_eq!(row.len(), full.len() - 1);
+        assert_eq!((&row[2], &row[3]), (owner, &"5".to_string()));
+
+        let stdout = String::from_utf8(nls(&["-g"], dir.path()).stdout).unwrap();
+        let row = long_row(&stdout, "visible.txt");
+        assert_eq!(row.len(), full.len() - 1);
+        assert_eq!((&row[2], &row[3]), (group, &"5".to_string()));
+
+        let stdout = String::from_utf8(nls(&["-o", "-g"], dir.path()).stdout).unwrap();
+        let row = long_row(&stdout, "visible.txt");
+        assert_eq!(row[2], "5");
+
+        // The rows are still aligned, the names start at the same column.
+        let stdout = String::from_utf8(nls(&["-o"], dir.path()).stdout).unwrap();
+        let starts: Vec<usize> = stdout
+            .lines()
+            .skip(1)
+            .map(|line| line.rfind(' ').unwrap())
+            .collect();
+        assert!(starts.windows(2).all(|w| w[0] == w[1]), "{}", stdout);
+    }
+
+    #[test]
+    fn test_time_styles() {
+        let dir = fixture();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        fs::File::options()
+            .write(true)
+            .open(dir.path().join("visible.txt"))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        let local: chrono::DateTime<chrono::Local> = mtime.into();
+
+        let cases = [
+            (vec!["-l"], local.format("%Y-%m-%d %H:%M:%S").to_string()),
+            (
+                vec!["-l", "--time-style=long-iso"],
+                local.format("%Y-%m-%d %H:%M:%S").to_string(),
+            ),
+            (
+                vec!["-l", "--time-style=iso"],
+                local.format("%Y-%m-%d").to_string(),
+            ),
+            (
+                vec!["-l", "--time-style=full-iso"],
+                local.format("%Y-%m-%d %H:%M:%S.123456789 %z").to_string(),
+            ),
+            (
+                vec!["-l", "--time-style=+%d/%m/%Y"],
+                local.format("%d/%m/%Y").to_string(),
+            ),
+            // '--full-time' implies '-l'.
+            (
+                vec!["--full-time"],
+                local.format("%Y-%m-%d %H:%M:%S.123456789 %z").to_string(),
+            ),
+        ];
+        for (args, expected) in cases {
+            let stdout = String::from_utf8(nls(&args, dir.path()).stdout).unwrap();
+            assert!(
+                stdout.contains(&format!(" {} visible.txt", expected)),
+                "{:?}: {}",
+                args,
+                stdout
+            );
+        }
+
+        for style in ["--time-style=%Y", "--time-style=+%Q", "--time-style=short"] {
+            let output = nls(&["-l", style], dir.path());
+            assert!(!output.status.success(), "{} was accepted", style);
+        }
+    }
+
+    #[test]
+    fn test_time_option_chooses_shown_and_sorted_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        // 'old' was modified earlier but accessed later than 'new'.
+        for (name, modified, accessed) in [("old", base, base + 3 * day), ("new", base + day, base)]
+        {
+            let file = fs::File::create(dir.path().join(name)).unwrap();
+            let times = fs::FileTimes::new()
+                .set_modified(modified)
+                .set_accessed(accessed);
+            file.set_times(times).unwrap();
+        }
+
+        let names = |args: &[&str]| -> Vec<String> {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            stdout.lines().map(|line| line.to_string()).collect()
+        };
+        // The newest file comes first, and '-r' puts the oldest one first.
+        assert_eq!(names(&["-t"]), ["new", "old"]);
+        assert_eq!(names(&["-t", "-r"]), ["old", "new"]);
+        assert_eq!(names(&["-t", "-u"]), ["old", "new"]);
+        assert_eq!(names(&["-t", "-u", "-r"]), ["new", "old"]);
+        assert_eq!(names(&["-t", "--time=atime"]), ["old", "new"]);
+        assert_eq!(names(&["-t", "--time=access"]), ["old", "new"]);
+        // '--time' wins over '-u'.
+        assert_eq!(names(&["-t", "-u", "--time=mtime"]), ["new", "old"]);
+
+        let format = |time: SystemTime| {
+            let time: chrono::DateTime<chrono::Local> = time.into();
+            time.format("%Y-%m-%d %H:%M:%S").to_string()
+        };
+        let stdout = String::from_utf8(nls(&["-l"], dir.path()).stdout).unwrap();
+        assert!(stdout.contains(&format!("{} old", format(base))));
+        let stdout = String::from_utf8(nls(&["-l", "-u"], dir.path()).stdout).unwrap();
+        assert!(stdout.contains(&format!("{} old", format(base + 3 * day))));
+
+        // The change time can't be set, it is read back from the metadata.
+        let metadata = fs::symlink_metadata(dir.path().join("old")).unwrap();
+        let ctime = SystemTime::UNIX_EPOCH
+            + Duration::new(metadata.ctime() as u64, metadata.ctime_nsec() as u32);
+        for args in [&["-l", "-c"][..], &["-l", "--time=ctime"]] {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            assert!(stdout.contains(&format!("{} old", format(ctime))));
+        }
+    }
+
+    #[test]
+    fn test_sort_by_change_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        // 'old' is modified earlier than 'new', but its metadata is changed the last.
+        for (name, modified) in [("old", base), ("new", base + Duration::from_secs(60))] {
+            let file = fs::File::create(dir.path().join(name)).unwrap();
+            file.set_times(fs::FileTimes::new().set_modified(modified))
+                .unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(20));
+        fs::set_permissions(dir.path().join("old"), fs::Permissions::from_mode(0o600)).unwrap();
+
+        let names = |args: &[&str]| -> Vec<String> {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            stdout.lines().map(|line| line.to_string()).collect()
+        };
+        assert_eq!(names(&["-t"]), ["new", "old"]);
+        // '-c' chooses the time that '-t' sorts on, in any order.
+        assert_eq!(names(&["-c", "-t"]), ["old", "new"]);
+        assert_eq!(names(&["-tc"]), ["old", "new"]);
+        assert_eq!(names(&["-c", "-t", "-r"]), ["new", "old"]);
+    }
+
+    #[test]
+    fn test_sort_by_access_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        // 'read' is accessed earlier than 'unread' until it is read.
+        for (name, time) in [("read", base), ("unread", base + day)] {
+            fs::write(dir.path().join(name), b"content").unwrap();
+            let file = fs::File::options()
+                .write(true)
+                .open(dir.path().join(name))
+                .unwrap();
+            file.set_times(fs::FileTimes::new().set_modified(time).set_accessed(time))
+                .unwrap();
+        }
+
+        let names = |args: &[&str]| -> Vec<String> {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            stdout.lines().map(|line| line.to_string()).collect()
+        };
+        assert_eq!(names(&["-u", "-t"]), ["unread", "read"]);
+
+        // The access time is not updated on the filesystems mounted with 'noatime'.
+        fs::read(dir.path().join("read")).unwrap();
+        let accessed = fs::metadata(dir.path().join("read"))
+            .unwrap()
+            .accessed()
+            .unwrap();
+        if accessed > base + day {
+            assert_eq!(names(&["-u", "-t"]), ["read", "unread"]);
+            // The last one of '-c' and '-u' wins.
+            assert_eq!(names(&["-c", "-u", "-t"]), ["read", "unread"]);
+        }
+        assert_eq!(names(&["-t"]), ["unread", "read"]);
+    }
+
+    #[test]
+    fn test_birth_time() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a", "b"] {
+            fs::File::create(dir.path().join(name)).unwrap();
+        }
+
+        let format = |time: SystemTime| {
+            let time: chrono::DateTime<chrono::Local> = time.into();
+            time.format("%Y-%m-%d %H:%M:%S%.9f").to_string()
+        };
+        let output = nls(&["-l", "--time=birth", "--full-time"], dir.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        // Not every filesystem records the birth time, then the modified time is shown
+        // and it is warned only once for all the files.
+        let metadata = fs::symlink_metadata(dir.path().join("a")).unwrap();
+        match metadata.created() {
+            Ok(created) => {
+                assert!(stdout.contains(&format(created)), "{}", stdout);
+                assert!(stderr.is_empty(), "{}", stderr);
+            }
+            Err(_) => {
+                assert!(
+                    stdout.contains(&format(metadata.modified().unwrap())),
+                    "{}",
+                    stdout
+                );
+                assert_eq!(stderr.matches("birth time").count(), 1, "{}", stderr);
+            }
+        }
+    }
+
+    #[test]
+    fn test_relative_time_in_long_listing() {
+        let dir = fixture();
+        let stdout = String::from_utf8(nls(&["-l", "--relative-time"], dir.path()).stdout).unwrap();
+        assert!(stdout.contains(" just now visible.txt"), "{}", stdout);
+    }
+
+    #[test]
+    fn test_color_option_controls_escape_sequences() {
+        let dir = fixture();
+
+        // The output is captured by a pipe, so 'auto' must not emit any escape sequence.
+        for args in [
+            &[][..],
+            &["--color=auto"],
+            &["--color=never"],
+            &["-l", "--color=never"],
+        ] {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            assert!(!stdout.contains('\x1b'), "nls {:?} emitted colors", args);
+        }
+
+        for args in [
+            &["--color=always"][..],
+            &["-l", "--color=always"],
+            &["-T", "--color=always"],
+        ] {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            assert!(stdout.contains('\x1b'), "nls {:?} emitted no colors", args);
+        }
+    }
+
+    #[test]
+    fn test_no_color_env_disables_colors_unless_forced() {
+        let dir = fixture();
+
+        // The names are colored on a terminal, unless 'NO_COLOR' is set.
+        let stdout = nls_on_tty(&[], dir.path(), 80);
+        assert!(stdout.contains('\x1b'));
+        let stdout = nls_on_tty_with_env(&[], dir.path(), 80, &[("NO_COLOR", "1")]);
+        assert!(!stdout.contains('\x1b'));
+        let stdout = nls_on_tty_with_env(&["--color=auto"], dir.path(), 80, &[("NO_COLOR", "1")]);
+        assert!(!stdout.contains('\x1b'));
+
+        let output = nls_with_env(&["--color=always"], dir.path(), &[("NO_COLOR", "1")]);
+        assert!(String::from_utf8(output.stdout).unwrap().contains('\x1b'));
+    }
+
+    #[test]
+    fn test_ls_colors_env_followed() {
+        let dir = fixture();
+        let envs = [("LS_COLORS", "di=01;34:*.TXT=01;31")];
+
+        let output = nls_with_env(&["--color=always"], dir.path(), &envs);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("\x1b[01;34msub\x1b[0m"));
+        assert!(stdout.contains("\x1b[01;31mvisible.txt\x1b[0m"));
+
+        let output = nls_with_env(&["--color=never"], dir.path(), &envs);
+        assert!(!String::from_utf8(output.stdout).unwrap().contains('\x1b'));
+    }
+
+    #[test]
+    fn test_extension_categories_colored() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "backup.zip",
+            "photo.PNG",
+            "song.mp3",
+            "main.rs",
+            "notes.txt",
+        ] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let stdout = String::from_utf8(nls(&["--color=always"], dir.path()).stdout).unwrap();
+        assert!(
+            stdout.contains("\x1b[1;31mbackup.zip\x1b[0m"),
+            "{:?}",
+            stdout
+        );
+        assert!(stdout.contains("\x1b[1;35mphoto.PNG\x1b[0m"));
+        assert!(stdout.contains("\x1b[96msong.mp3\x1b[0m"));
+        assert!(stdout.contains("\x1b[93mmain.rs\x1b[0m"));
+        assert!(stdout.contains("\x1b[37mnotes.txt\x1b[0m"));
+
+        // 'LS_COLORS' still wins over the categories.
+        let envs = [("LS_COLORS", "*.zip=01;34")];
+        let output = nls_with_env(&["--color=always"], dir.path(), &envs);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("\x1b[01;34mbackup.zip\x1b[0m"));
+    }
+
+    #[test]
+    fn test_human_readable_size_unit_boundaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cases = [
+            ("a", 1023, "1023B"),
+            ("b", 1024, "1KiB"),
+            ("c", 1025, "1KiB"),
+            ("d", 1024 * 1024, "1MiB"),
+            ("e", 1024 * 1024 * 1024, "1GiB"),
+            // They are rounded up to the next unit, not shown as '1024KiB' and '1024MiB'.
+            ("f", 1024 * 1024 - 1, "1MiB"),
+            ("g", 1024 * 1024 * 1024 - 1, "1GiB"),
+        ];
+        // Sparse files are enough, only the apparent size matters.
+        for (name, size, _) in cases {
+            fs::File::create(dir.path().join(name))
+                .unwrap()
+                .set_len(size)
+                .unwrap();
+        }
+
+        let stdout = String::from_utf8(nls(&["-l", "-h"], dir.path()).stdout).unwrap();
+        for (name, _, expected) in cases {
+            assert_eq!(long_row(&stdout, name)[4], expected);
+        }
+    }
+
+    #[test]
+    fn test_size_column_right_aligned() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, size) in [("tiny", 1), ("small", 1500), ("big", 123_456_789_012)] {
+            fs::File::create(dir.path().join(name))
+                .unwrap()
+                .set_len(size)
+                .unwrap();
+        }
+
+        for args in [&["-l"][..], &["-l", "-h"], &["-l", "--bytes"]] {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            let lines: Vec<&str> = stdout.lines().skip(1).collect();
+
+            // The sizes end at the same column, right before the times.
+            let ends: Vec<usize> = lines
+                .iter()
+                .map(|line| {
+                    let row: Vec<&str> = line.split_whitespace().collect();
+                    line.find(&format!(" {} ", row[5])).unwrap()
+                })
+                .collect();
+            assert!(ends.windows(2).all(|w| w[0] == w[1]), "{}", stdout);
+
+            // The widest size is only one space away from the group, nothing is wasted.
+            let widest = lines.iter().find(|line| line.ends_with(" big")).unwrap();
+            let row: Vec<&str> = widest.split_whitespace().collect();
+            assert!(
+                widest.contains(&format!(" {} {} ", row[3], row[4])),
+                "{}",
+                stdout
+            );
+        }
+    }
+
+    #[test]
+    fn test_bytes_with_thousands_separators() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::File::create(dir.path().join("f"))
+            .unwrap()
+            .set_len(1_048_576)
+            .unwrap();
+
+        let stdout = String::from_utf8(nls(&["-l", "--bytes"], dir.path()).stdout).unwrap();
+        assert_eq!(long_row(&stdout, "f")[4], "1,048,576");
+
+        // It makes no sense with the human readable sizes.
+        let output = nls(&["-l", "--bytes", "-h"], dir.path());
+        assert_eq!(output.status.code(), Some(2));
+    }
+
+    #[test]
+    fn test_si_sizes_in_powers_of_1000() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::File::create(dir.path().join("f"))
+            .unwrap()
+            .set_len(1500)
+            .unwrap();
+
+        let stdout = String::from_utf8(nls(&["-l", "--si"], dir.path()).stdout).unwrap();
+        assert_eq!(long_row(&stdout, "f")[4], "1.5kB");
+        let stdout = String::from_utf8(nls(&["-l", "-h"], dir.path()).stdout).unwrap();
+        assert_eq!(long_row(&stdout, "f")[4], "1.46KiB");
+        // '--si' wins over '-h'.
+        let stdout = String::from_utf8(nls(&["-l", "-h", "--si"], dir.path()).stdout).unwrap();
+        assert_eq!(long_row(&stdout, "f")[4], "1.5kB");
+    }
+
+    #[test]
+    fn test_block_size_scales_sizes() {
+        let dir = fixture();
+        let path = dir.path().join("sub");
+
+        let size = |args: &[&str]| {
+            let stdout = String::from_utf8(nls(args, &path).stdout).unwrap();
+            long_row(&stdout, "inner.txt")[4].clone()
+        };
+        assert_eq!(size(&["-l", "-k"]), "2");
+        assert_eq!(size(&["-l", "--block-size=512"]), "4");
+        assert_eq!(size(&["-l", "--block-size=1KB"]), "3");
+        assert_eq!(size(&["-l", "--block-size=human-readable"]), "2KiB");
+        // The block size wins over '-h', and '--block-size' wins over '-k'.
+        assert_eq!(size(&["-l", "-h", "-k"]), "2");
+        assert_eq!(size(&["-l", "-k", "--block-size=512"]), "4");
+
+        // The total is counted in the block size as well.
+        let blocks = fs::symlink_metadata(path.join("inner.txt"))
+            .unwrap()
+            .blocks();
+        let stdout = String::from_utf8(nls(&["-l", "--block-size=512"], &path).stdout).unwrap();
+        assert_eq!(stdout.lines().next().unwrap(), format!("total {}", blocks));
+
+        assert!(!nls(&["-l", "--block-size=abc"], &path).status.success());
+    }
+
+    #[test]
+    fn test_sort_by_time_uses_sub_second_precision() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+
+        // Both files fall into the same second, so only the raw timestamps can order them.
+        let newer = fs::File::create(dir.path().join("a_newer")).unwrap();
+        newer.set_modified(now + Duration::from_millis(5)).unwrap();
+        let older = fs::File::create(dir.path().join("b_older")).unwrap();
+        older.set_modified(now).unwrap();
+
+        let stdout = String::from_utf8(nls(&["-t"], dir.path()).stdout).unwrap();
+        assert!(stdout.find("a_newer").unwrap() < stdout.find("b_older").unwrap());
+    }
+
+    #[test]
+    fn test_nonexistent_path_reports_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+
+        let output = nls(&[], &missing);
+        assert_eq!(output.status.code(), Some(2));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert_eq!(
+            stderr.trim_end(),
+            format!(
+                "nls: cannot access '{}': No such file or directory",
+                missing.display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_unreadable_directory_reports_error() {
+        // Root can read any directory, so there is nothing to deny.
+        if users::get_current_uid() == 0 {
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        let output = nls(&[], &locked);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert_eq!(output.status.code(), Some(2));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("nls: cannot open directory"));
+        assert!(stderr.trim_end().ends_with("Permission denied"));
+    }
+
+    #[test]
+    fn test_recursive_unreadable_subdirectory_fails() {
+        // Root can read any directory, so there is nothing to deny.
+        if users::get_current_uid() == 0 {
+            return;
+        }
+
+        let dir = nested_fixture();
+        let locked = dir.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        let output = nls(&["-R"], dir.path());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // The others are still listed, but the command fails like GNU ls.
+        assert_eq!(output.status.code(), Some(2));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("nls: cannot open directory"));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("c.txt"));
+    }
+
+    #[test]
+    fn test_json_tree_unreadable_branch() {
+        // Root can read any directory, so there is nothing to deny.
+        if users::get_current_uid() == 0 {
+            return;
+        }
+
+        let dir = fixture();
+        let locked = dir.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        let output = nls(&["-T", "--json"], dir.path());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // The directory is reported and has no children, its siblings are still in the tree.
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("nls: cannot open directory"));
+        let tree: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let children = tree["children"].as_array().unwrap();
+        let child = |name: &str| {
+            children
+                .iter()
+                .find(|child| child["name"] == name)
+                .unwrap()
+                .clone()
+        };
+        assert!(child("locked").get("children").is_none());
+        assert_eq!(child("sub")["children"][0]["name"], "inner.txt");
+    }
+
+    #[test]
+    fn test_tree_unreadable_branch() {
+        // Root can read any directory, so there is nothing to deny.
+        if users::get_current_uid() == 0 {
+            return;
+        }
+
+        let dir = fixture();
+        let locked = dir.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        let output = nls(&["-T", "--color=always"], dir.path());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // The branch tells the error in red, and the others are still drawn.
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            stdout.contains("\x1b[31mPermission denied\x1b[0m"),
+            "{}",
+            stdout
+        );
+        assert!(stdout.contains("inner.txt"));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_entry_metadata_error_skips_entry() {
+        // The fd of the directory itself is closed once it has been read,
+        // so its entry is gone before its metadata is read.
+        let output = nls(&["-1"], Path::new("/proc/self/fd"));
+        assert!(output.status.success());
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let names: Vec<&str> = stdout.lines().collect();
+        assert!(["0", "1", "2"].iter().all(|fd| names.contains(fd)));
+
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("nls: cannot access"));
+        assert!(stderr.trim_end().ends_with("No such file or directory"));
+    }
+
+    // Build a nested fixture: root/{a.txt, .hid/x, sub/{b.txt, deep/c.txt}}.
+    fn nested_fixture() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sub").join("deep")).unwrap();
+        fs::create_dir(dir.path().join(".hid")).unwrap();
+        fs::write(dir.path().join("a.txt"), b"a").unwrap();
+        fs::write(dir.path().join(".hid").join("x"), b"x").unwrap();
+        fs::write(dir.path().join("sub").join("b.txt"), b"b").unwrap();
+        fs::write(dir.path().join("sub").join("deep").join("c.txt"), b"c").unwrap();
+        dir
+    }
+
+    // Collect the 'path:' headers of a recursive listing.
+    fn headers(stdout: &str) -> Vec<String> {
+        stdout
+            .lines()
+            .filter(|line| line.ends_with(':'))
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_recursive_listing_headers() {
+        let dir = nested_fixture();
+        let root = dir.path().display().to_string();
+
+        let stdout = String::from_utf8(nls(&["-R"], dir.path()).stdout).unwrap();
+        assert_eq!(
+            headers(&stdout),
+            [
+                format!("{}:", root),
+                format!("{}/sub:", root),
+                format!("{}/sub/deep:", root),
+            ]
+        );
+        assert!(stdout.contains("c.txt"));
+        // Every directory block is separated by a blank line.
+        assert!(stdout.contains(&format!("\n\n{}/sub:", root)));
+
+        // Hidden directories are only descended into with '-a'.
+        let stdout = String::from_utf8(nls(&["-R", "-a"], dir.path()).stdout).unwrap();
+        assert!(headers(&stdout).contains(&format!("{}/.hid:", root)));
+    }
+
+    #[test]
+    fn test_recursive_listing_honors_depth() {
+        let dir = nested_fixture();
+        let root = dir.path().display().to_string();
+
+        let stdout = String::from_utf8(nls(&["-R", "--depth", "1"], dir.path()).stdout).unwrap();
+        assert_eq!(
+            headers(&stdout),
+            [format!("{}:", root), format!("{}/sub:", root)]
+        );
+        assert!(!stdout.contains("c.txt"));
+    }
+
+    #[test]
+    fn test_tree_conflicts_with_recursive() {
+        let dir = nested_fixture();
+
+        // The tree already goes into the subdirectories, so '-R' makes no sense with it.
+        for args in [
+            &["-T", "-R"][..],
+            &["-l", "-R", "-T"],
+            &["-T", "-R", "--json"],
+        ] {
+            let output = nls(args, dir.path());
+            assert!(!output.status.success());
+            assert!(output.stdout.is_empty());
+            assert!(String::from_utf8(output.stderr)
+                .unwrap()
+                .contains("cannot be used with"));
+        }
+    }
+
+    #[test]
+    fn test_json_of_several_paths_is_one_document() {
+        let dir = nested_fixture();
+        let sub = dir.path().join("sub");
+        let file = dir.path().join("a.txt");
+        let key = |path: &Path| path.to_string_lossy().into_owned();
+
+        // The listings are keyed by the paths, a file gets its info and a directory gets its content.
+        let args = ["--json", sub.to_str().unwrap(), file.to_str().unwrap()];
+        let output = nls(&args, dir.path());
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+        let object = json.as_object().unwrap();
+        assert_eq!(object.len(), 3);
+        assert_eq!(json[key(&file)]["name"], "a.txt");
+        assert_eq!(json[key(&sub)][0]["name"], "b.txt");
+        assert_eq!(json[key(dir.path())].as_array().unwrap().len(), 2);
+
+        // The trees are keyed the same way.
+        let output = nls(&["--json", "-T", sub.to_str().unwrap()], dir.path());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+        assert!(json[key(&sub)]["children"].is_array());
+        assert!(json[key(dir.path())]["children"].is_array());
+    }
+
+    #[test]
+    fn test_json_conflicts_with_recursive() {
+        let dir = nested_fixture();
+
+        // The flat JSON has no room for the subdirectories, '-T' nests them instead.
+        let output = nls(&["--json", "-R"], dir.path());
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("cannot be used with"));
+
+        let output = nls(&["--json", "-T"], dir.path());
+        assert!(output.status.success());
+        let tree: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert!(tree["children"].is_array());
+    }
+
+    #[test]
+    fn test_depth_levels() {
+        let dir = nested_fixture();
+        let root = dir.path().display().to_string();
+
+        let tree = |depth: &[&str]| -> Vec<String> {
+            let args = [&["-T"], depth].concat();
+            let stdout = String::from_utf8(nls(&args, dir.path()).stdout).unwrap();
+            stdout
+                .lines()
+                .skip(1)
+                .map(|line| {
+                    line.trim_start_matches(['│', '├', '└', '─', ' '])
+                        .to_string()
+                })
+                .collect()
+        };
+        let recursive = |depth: &[&str]| -> Vec<String> {
+            let args = [&["-R"], depth].concat();
+            headers(&String::from_utf8(nls(&args, dir.path()).stdout).unwrap())
+        };
+        let json_tree = |depth: &[&str]| -> String {
+            let args = [&["-T", "--json"], depth].concat();
+            String::from_utf8(nls(&args, dir.path()).stdout).unwrap()
+        };
+
+        // Depth 0 shows just the entries of the directory.
+        assert_eq!(tree(&["--depth", "0"]), ["a.txt", "sub"]);
+        assert_eq!(recursive(&["--depth", "0"]), [format!("{}:", root)]);
+        assert!(!json_tree(&["--depth", "0"]).contains("b.txt"));
+
+        // Every level of depth goes one directory deeper.
+        assert_eq!(tree(&["--depth", "1"]), ["a.txt", "sub", "b.txt", "deep"]);
+        assert_eq!(
+            recursive(&["--depth", "1"]),
+            [format!("{}:", root), format!("{}/sub:", root)]
+        );
+        let json = json_tree(&["--depth", "1"]);
+        assert!(json.contains("b.txt") && !json.contains("c.txt"));
+
+        // There is no limit without '--depth' or with '--depth=inf'.
+        for depth in [&[][..], &["--depth=inf"]] {
+            assert_eq!(tree(depth), ["a.txt", "sub", "b.txt", "deep", "c.txt"]);
+            assert_eq!(recursive(depth).len(), 3);
+            assert!(json_tree(depth).contains("c.txt"));
+        }
+
+        let output = nls(&["-T", "--depth", "deep"], dir.path());
+        assert!(!output.status.success());
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("invalid depth 'deep'"));
+    }
+
+    #[test]
+    fn test_one_name_per_line() {
+        let dir = fixture();
+
+        // The output is piped, so one name per line is the default as well.
+        for args in [&[][..], &["-1"]] {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            assert_eq!(stdout.lines().collect::<Vec<_>>(), ["sub", "visible.txt"]);
+        }
+
+        let stdout = String::from_utf8(nls(&["-1", "-A"], dir.path()).stdout).unwrap();
+        assert_eq!(stdout.lines().count(), 3);
+
+        // Colors don't break the lines apart.
+        let stdout = String::from_utf8(nls(&["-1", "--color=always"], dir.path()).stdout).unwrap();
+        assert_eq!(stdout.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_piped_output_is_plain() {
+        let dir = fixture();
+
+        // Nothing is colored or laid out in a grid through a pipe.
+        let stdout = String::from_utf8(nls(&[], dir.path()).stdout).unwrap();
+        assert!(!stdout.contains('\x1b'));
+        assert_eq!(stdout, "sub\nvisible.txt\n");
+
+        // The explicit options still win.
+        let stdout = String::from_utf8(nls(&["--color=always"], dir.path()).stdout).unwrap();
+        assert!(stdout.contains('\x1b'));
+        let stdout = String::from_utf8(nls(&["-C"], dir.path()).stdout).unwrap();
+        assert_eq!(stdout, "sub          visible.txt\n");
+        // The grid fits in 'COLUMNS' when there is no terminal to ask.
+        let output = nls_with_env(&["-C"], dir.path(), &[("COLUMNS", "20")]);
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "sub\nvisible.txt\n"
+        );
+        let stdout = String::from_utf8(nls(&["-C", "-1"], dir.path()).stdout).unwrap();
+        assert_eq!(stdout.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_names_laid_out_in_grid_on_terminal() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a1", "a2", "a3", "a4", "a5", "longest_name"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        // Every column is 14 chars wide, so 30 columns of terminal fit 2 columns of names,
+        // and the names are filled from top to bottom.
+        let stdout = nls_on_tty(&["--color=never"], dir.path(), 30);
+        assert_eq!(
+            stdout.lines().collect::<Vec<_>>(),
+            [
+                "a1            a4",
+                "a2            a5",
+                "a3            longest_name"
+            ]
+        );
+
+        // A wide terminal puts every name on one line.
+        let stdout = nls_on_tty(&["--color=never"], dir.path(), 200);
+        assert_eq!(stdout.lines().count(), 1);
+
+        // Colors don't change the layout.
+        let stdout = nls_on_tty(&["--color=always"], dir.path(), 30);
+        assert_eq!(stdout.lines().count(), 3);
+    }
+
+    #[test]
+    fn test_width_option() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 1..=15 {
+            fs::write(dir.path().join(format!("file{:02}", i)), b"").unwrap();
+        }
+
+        // Every column is 8 chars wide, so 40 columns fit 5 columns of names, even through a pipe.
+        let stdout = String::from_utf8(nls(&["-w", "40"], dir.path()).stdout).unwrap();
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "file01  file04  file07  file10  file13");
+
+        // 0 means no limit, so every name is on one line.
+        let stdout = String::from_utf8(nls(&["-w", "0"], dir.path()).stdout).unwrap();
+        assert_eq!(stdout.lines().count(), 1);
+
+        // The width wins over the one of the terminal.
+        let stdout = nls_on_tty(&["--color=never", "-w", "40"], dir.path(), 200);
+        assert_eq!(stdout.lines().count(), 3);
+    }
+
+    #[test]
+    fn test_across() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a1", "a2", "a3", "a4", "a5", "longest_name"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        // Every column is 14 chars wide, so 30 columns fit 2 columns of names filled from left to right.
+        let stdout = String::from_utf8(nls(&["-x", "-w", "30"], dir.path()).stdout).unwrap();
+        assert_eq!(
+            stdout.lines().collect::<Vec<_>>(),
+            [
+                "a1            a2",
+                "a3            a4",
+                "a5            longest_name"
+            ]
+        );
+
+        // Colors don't change the order.
+        let stdout = nls(&["-x", "-w", "30", "--color=always"], dir.path()).stdout;
+        let stdout = strip_escapes(&String::from_utf8(stdout).unwrap());
+        assert!(stdout.starts_with("a1            a2\n"));
+
+        // The names are laid out in 80 columns through a pipe, that is 5 columns of names,
+        // and '-1' wins when it comes later.
+        let stdout = String::from_utf8(nls(&["-x"], dir.path()).stdout).unwrap();
+        assert_eq!(stdout.lines().count(), 2);
+        let stdout = String::from_utf8(nls(&["-x", "-1"], dir.path()).stdout).unwrap();
+        assert_eq!(stdout.lines().count(), 6);
+    }
+
+    // Remove the ANSI escape sequences from the output.
+    fn strip_escapes(s: &str) -> String {
+        let mut plain = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' && chars.peek() == Some(&']') {
+                // The links end with '\x1b\\'.
+                while let Some(c) = chars.next() {
+                    if c == '\x1b' && chars.next_if_eq(&'\\').is_some() {
+                        break;
+                    }
+                }
+            } else if c == '\x1b' {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            } else {
+                plain.push(c);
+            }
+        }
+        plain
+    }
+
+    #[test]
+    fn test_grid_alignment_ignores_color_escapes() {
+        let dir = tempfile::tempdir().unwrap();
+        // A directory and a file of the same name length get different colors.
+        fs::create_dir(dir.path().join("dir_a")).unwrap();
+        fs::write(dir.path().join("file1"), b"").unwrap();
+        fs::write(dir.path().join("file2"), b"").unwrap();
+
+        let plain = nls_on_tty(&["--color=never"], dir.path(), 80);
+        let colored = nls_on_tty(&["--color=always"], dir.path(), 80);
+
+        assert!(colored.contains('\x1b'));
+        assert_eq!(strip_escapes(&colored), plain);
+        assert_eq!(plain.find("file1"), Some(7));
+    }
+
+    // Build a fixture holding every kind of entry that has an indicator.
+    fn typed_fixture() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("dir")).unwrap();
+        fs::write(dir.path().join("plain"), b"").unwrap();
+        fs::write(dir.path().join("exec"), b"").unwrap();
+        fs::set_permissions(dir.path().join("exec"), fs::Permissions::from_mode(0o755)).unwrap();
+        symlink("plain", dir.path().join("link")).unwrap();
+        let fifo = CString::new(dir.path().join("pipe").as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+        UnixListener::bind(dir.path().join("sock")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_special_kinds_colored_distinctly() {
+        let dir = typed_fixture();
+        symlink("missing", dir.path().join("dangling")).unwrap();
+        for (name, mode) in [("tmp", 0o1777), ("shared", 0o777), ("sticky", 0o1755)] {
+            fs::create_dir(dir.path().join(name)).unwrap();
+            fs::set_permissions(dir.path().join(name), fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        let stdout = String::from_utf8(nls(&["--color=always"], dir.path()).stdout).unwrap();
+        let expected = [
+            ("\x1b[1;32m", "exec"),
+            ("\x1b[37m", "plain"),
+            ("\x1b[31m", "dangling"),
+            ("\x1b[34m", "link"),
+            ("\x1b[36m", "dir"),
+            ("\x1b[42;30m", "tmp"),
+            ("\x1b[42;34m", "shared"),
+            ("\x1b[44;37m", "sticky"),
+            ("\x1b[40;33m", "pipe"),
+            ("\x1b[1;35m", "sock"),
+        ];
+        for (color, name) in expected {
+            let line = format!("{}{}\x1b[0m", color, name);
+            assert!(
+                stdout.lines().any(|l| l == line),
+                "{:?} not in {:?}",
+                line,
+                stdout
+            );
+        }
+    }
+
+    #[test]
+    fn test_device_colored() {
+        let output = nls(&["--color=always"], Path::new("/dev/null"));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(stdout, "\x1b[1;40;33mnull\x1b[0m\n");
+    }
+
+    #[test]
+    fn test_classify_appends_indicators() {
+        let dir = typed_fixture();
+
+        let stdout = String::from_utf8(nls(&["-F"], dir.path()).stdout).unwrap();
+        assert_eq!(
+            stdout.lines().collect::<Vec<_>>(),
+            ["dir/", "exec*", "link@", "pipe|", "plain", "sock="]
+        );
+
+        // The indicator follows the name in long mode and is not colored like the name.
+        let stdout =
+            String::from_utf8(nls(&["-l", "-F", "--color=always"], dir.path()).stdout).unwrap();
+        assert!(stdout.contains("dir\x1b[0m/"));
+
+        let stdout = String::from_utf8(nls(&["-l", "-F"], dir.path()).stdout).unwrap();
+        assert!(long_row(&stdout, "exec*")[0].starts_with("-rwx"));
+
+        // A link is marked by the indicator of its target after the arrow in long mode, like GNU 'ls -lF'.
+        symlink("dir", dir.path().join("dlink")).unwrap();
+        symlink("exec", dir.path().join("xlink")).unwrap();
+        symlink("missing", dir.path().join("dangling")).unwrap();
+        let stdout = String::from_utf8(nls(&["-l", "-F"], dir.path()).stdout).unwrap();
+        for entry in [
+            " dlink -> dir/",
+            " xlink -> exec*",
+            " link -> plain",
+            " dangling -> missing",
+        ] {
+            assert!(
+                stdout.lines().any(|line| line.ends_with(entry)),
+                "{}",
+                entry
+            );
+        }
+        assert!(!stdout.contains("@ ->"));
+        let stdout = String::from_utf8(nls(&["-l", "-p"], dir.path()).stdout).unwrap();
+        assert!(stdout.lines().any(|line| line.ends_with(" dlink -> dir/")));
+
+        let stdout = String::from_utf8(nls(&[], dir.path()).stdout).unwrap();
+        assert!(stdout
+            .lines()
+            .all(|line| !line.ends_with(['/', '*', '@', '|', '='])));
+    }
+
+    #[test]
+    fn test_slash_only_appended_to_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("subdir")).unwrap();
+        fs::write(dir.path().join("file"), b"").unwrap();
+        symlink("subdir", dir.path().join("link")).unwrap();
+
+        let stdout = String::from_utf8(nls(&["-p"], dir.path()).stdout).unwrap();
+        assert_eq!(
+            stdout.lines().collect::<Vec<_>>(),
+            ["file", "link", "subdir/"]
+        );
+
+        // '-F' wins when both are given.
+        let stdout = String::from_utf8(nls(&["-p", "-F"], dir.path()).stdout).unwrap();
+        assert_eq!(
+            stdout.lines().collect::<Vec<_>>(),
+            ["file", "link@", "subdir/"]
+        );
+    }
+
+    #[test]
+    fn test_group_directories_first() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a_file"), vec![0u8; 300]).unwrap();
+        fs::write(dir.path().join("c_file"), vec![0u8; 100]).unwrap();
+        fs::create_dir(dir.path().join("b_dir")).unwrap();
+        fs::create_dir(dir.path().join("d_dir")).unwrap();
+        fs::write(dir.path().join("d_dir").join("f"), b"").unwrap();
+
+        let list = |args: &[&str]| {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            stdout.lines().map(|l| l.to_string()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            list(&["--group-directories-first"]),
+            ["b_dir", "d_dir", "a_file", "c_file"]
+        );
+        assert_eq!(
+            list(&["--group-directories-first", "-r"]),
+            ["d_dir", "b_dir", "c_file", "a_file"]
+        );
+        // Both directories have the same size, so only the files have a fixed order.
+        let by_size = list(&["--group-directories-first", "-s"]);
+        assert!(by_size[..2].iter().all(|name| name.ends_with("_dir")));
+        assert_eq!(by_size[2..], ["a_file", "c_file"]);
+    }
+
+    #[test]
+    fn test_sort_by_size_largest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), vec![0u8; 100]).unwrap();
+        fs::write(dir.path().join("b"), vec![0u8; 10]).unwrap();
+        fs::write(dir.path().join("c"), vec![0u8; 1000]).unwrap();
+
+        let list = |args: &[&str]| {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            stdout.lines().map(|l| l.to_string()).collect::<Vec<_>>()
+        };
+
+        for args in [
+            &["-S"][..],
+            &["-s"],
+            &["--sort=size"],
+            &["-t", "--sort=size"],
+        ] {
+            assert_eq!(list(args), ["c", "a", "b"], "nls {:?}", args);
+        }
+        assert_eq!(list(&["-S", "-r"]), ["b", "a", "c"]);
+        assert_eq!(list(&["-S", "--sort=name"]), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_sort_ties_broken_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let names: Vec<String> = (0..10).rev().map(|i| format!("file{}", i)).collect();
+        for name in &names {
+            let file = fs::File::create(dir.path().join(name)).unwrap();
+            file.set_modified(time).unwrap();
+        }
+
+        let mut expected = names.clone();
+        expected.sort();
+        for args in [&["-S"][..], &["-t"]] {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            assert_eq!(
+                stdout.lines().collect::<Vec<_>>(),
+                expected,
+                "nls {:?}",
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn test_sort_by_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "b.txt", "a.rs", "c.rs", "Makefile", ".bashrc", "z.tar.gz", "README",
+        ] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let expected = [
+            ".bashrc", "Makefile", "README", "z.tar.gz", "a.rs", "c.rs", "b.txt",
+        ];
+        for args in [&["-A", "-X"][..], &["-A", "--sort=extension"]] {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            assert_eq!(stdout.lines().collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["banana", "Cherry", "Apple", "apple.rs", "Zebra.rs"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let list = |args: &[&str]| {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            stdout.lines().map(|l| l.to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            list(&[]),
+            ["Apple", "Cherry", "Zebra.rs", "apple.rs", "banana"]
+        );
+        assert_eq!(
+            list(&["--ignore-case"]),
+            ["Apple", "apple.rs", "banana", "Cherry", "Zebra.rs"]
+        );
+
+        // The names with the same extension are sorted regardless of case as well.
+        assert_eq!(
+            list(&["-X", "--ignore-case"]),
+            ["Apple", "banana", "Cherry", "apple.rs", "Zebra.rs"]
+        );
+    }
+
+    #[test]
+    fn test_sort_by_version() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["img10", "img2", "img100", "img1", "img02.png"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let expected = ["img1", "img2", "img02.png", "img10", "img100"];
+        for args in [&["-v"][..], &["--sort=version"]] {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            assert_eq!(stdout.lines().collect::<Vec<_>>(), expected);
+        }
+
+        let stdout = String::from_utf8(nls(&["-v", "-r"], dir.path()).stdout).unwrap();
+        let mut reversed = expected;
+        reversed.reverse();
+        assert_eq!(stdout.lines().collect::<Vec<_>>(), reversed);
+    }
+
+    #[test]
+    fn test_unsorted() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..20 {
+            fs::write(dir.path().join(format!("file{}", i)), b"").unwrap();
+        }
+
+        let expected: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        for args in [&["-U"][..], &["--sort=none"]] {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            assert_eq!(stdout.lines().collect::<Vec<_>>(), expected);
+        }
+
+        // '-U' streams the names, and the details are buffered, they are in the same order.
+        let stdout = String::from_utf8(nls(&["-U", "-l"], dir.path()).stdout).unwrap();
+        let names: Vec<_> = stdout
+            .lines()
+            .skip(1)
+            .map(|line| line.rsplit(' ').next().unwrap())
+            .collect();
+        assert_eq!(names, expected);
+
+        let stdout = String::from_utf8(nls(&["-U", "-r"], dir.path()).stdout).unwrap();
+        let mut reversed = expected;
+        reversed.reverse();
+        assert_eq!(stdout.lines().collect::<Vec<_>>(), reversed);
+    }
+
+    #[test]
+    fn test_json_output() {
+        let dir = fixture();
+
+        let output = nls(&["--json", "-l", "--color=always"], dir.path());
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let entries = json.as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let visible = entries.iter().find(|e| e["name"] == "visible.txt").unwrap();
+        assert_eq!(visible["size"], 5);
+        assert_eq!(visible["file_type"], "file");
+        assert_eq!(visible["is_hidden"], false);
+        assert!(visible["modified_time"].is_string());
+
+        let output = nls(&["--json", "-A"], dir.path());
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 3);
+
+        let output = nls(&["--json", "-a"], dir.path());
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_tree_connectors() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sub").join("deep")).unwrap();
+        for name in ["a.txt", "sub/b.txt", "sub/deep/c.txt", "sub/z.txt"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let root = dir
+            .path()
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+
+        let stdout = String::from_utf8(nls(&["-T"], dir.path()).stdout).unwrap();
+        let expected = [
+            root.as_str(),
+            "├── a.txt",
+            "└── sub",
+            "    ├── b.txt",
+            "    ├── deep",
+            "    │   └── c.txt",
+            "    └── z.txt",
+        ];
+        assert_eq!(stdout.lines().collect::<Vec<_>>(), expected);
+
+        let stdout = String::from_utf8(nls(&["-T", "--ascii"], dir.path()).stdout).unwrap();
+        let expected = [
+            root.as_str(),
+            "|-- a.txt",
+            "`-- sub",
+            "    |-- b.txt",
+            "    |-- deep",
+            "    |   `-- c.txt",
+            "    `-- z.txt",
+        ];
+        assert_eq!(stdout.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_tree_honors_all() {
+        let dir = fixture();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join(".git").join("HEAD"), b"").unwrap();
+
+        let stdout = String::from_utf8(nls(&["-T"], dir.path()).stdout).unwrap();
+        assert!(stdout.contains("visible.txt"));
+        assert!(!stdout.contains(".git"));
+        assert!(!stdout.contains(".hidden"));
+        assert!(!stdout.contains("HEAD"));
+
+        for args in [&["-T", "-a"][..], &["-T", "-A"]] {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            assert!(stdout.contains("── .git\n"), "{}", stdout);
+            assert!(stdout.contains("── HEAD\n"), "{}", stdout);
+            assert!(stdout.contains("── .hidden\n"), "{}", stdout);
+        }
+    }
+
+    #[test]
+    fn test_tree_children_sorted_by_option() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        for (name, size) in [("a", 100), ("b", 10), ("c", 1000)] {
+            fs::write(dir.path().join("sub").join(name), vec![0u8; size]).unwrap();
+        }
+
+        let children = |args: &[&str]| -> Vec<String> {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            stdout
+                .lines()
+                .filter(|line| line.starts_with("    "))
+                .map(|line| {
+                    line.trim_start_matches(['│', '├', '└', '─', ' '])
+                        .to_string()
+                })
+                .collect()
+        };
+
+        assert_eq!(children(&["-T"]), ["a", "b", "c"]);
+        assert_eq!(children(&["-T", "-s"]), ["c", "a", "b"]);
+        assert_eq!(children(&["-T", "-S", "-r"]), ["b", "a", "c"]);
+        assert_eq!(children(&["-T", "-r"]), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn test_tree_sizes() {
+        let dir = fixture();
+
+        // The files get their own sizes, and the directories get the sizes of their content.
+        let stdout = String::from_utf8(nls(&["-T", "-l"], dir.path()).stdout).unwrap();
+        assert!(stdout.contains("└── inner.txt [2048]\n"), "{}", stdout);
+        assert!(stdout.contains("├── sub [2048]\n"), "{}", stdout);
+        assert!(stdout.contains("└── visible.txt [5]\n"), "{}", stdout);
+        // Only the entries in the tree are counted, the hidden file is not unless '-a' shows it.
+        assert!(stdout.lines().next().unwrap().ends_with(" [2053]"));
+        let stdout = String::from_utf8(nls(&["-T", "-l", "-a"], dir.path()).stdout).unwrap();
+        assert!(stdout.lines().next().unwrap().ends_with(" [2059]"));
+
+        // The directories beyond the depth keep their own sizes.
+        let sub_size = fs::metadata(dir.path().join("sub")).unwrap().len();
+        let stdout =
+            String::from_utf8(nls(&["-T", "-l", "--depth", "0"], dir.path()).stdout).unwrap();
+        assert!(
+            stdout.contains(&format!("├── sub [{}]\n", sub_size)),
+            "{}",
+            stdout
+        );
+        assert!(stdout
+            .lines()
+            .next()
+            .unwrap()
+            .ends_with(&format!(" [{}]", sub_size + 5)));
+
+        // '--total-size' counts all of their content like 'du'.
+        let args = ["-T", "-l", "--depth", "0", "--total-size"];
+        let stdout = String::from_utf8(nls(&args, dir.path()).stdout).unwrap();
+        assert!(stdout.contains("├── sub [2048]\n"), "{}", stdout);
+        assert!(stdout.lines().next().unwrap().ends_with(" [2059]"));
+
+        let stdout = String::from_utf8(nls(&["-T", "-l", "-h"], dir.path()).stdout).unwrap();
+        assert!(stdout.contains("└── inner.txt [2KiB]\n"), "{}", stdout);
+        assert!(stdout.contains("└── visible.txt [5B]\n"), "{}", stdout);
+
+        // There are no sizes without '-l'.
+        let stdout = String::from_utf8(nls(&["-T"], dir.path()).stdout).unwrap();
+        assert!(!stdout.contains('['));
+    }
+
+    #[test]
+    fn test_mounts() {
+        let dir = fixture();
+        let stdout = String::from_utf8(nls(&["--mounts", "-l"], dir.path()).stdout).unwrap();
+        assert!(!stdout.contains("[mount point]"));
+
+        // '/proc' is usually a filesystem of its own, the test is skipped where it isn't.
+        let proc = Path::new("/proc");
+        let device = |path: &Path| fs::metadata(path).map(|m| m.dev()).ok();
+        if device(proc).is_none() || device(proc) == device(Path::new("/")) {
+            return;
+        }
+
+        let stdout = String::from_utf8(nls(&["--mounts", "-d"], proc).stdout).unwrap();
+        assert_eq!(stdout.trim_end(), "proc [mount point]");
+
+        // The tree goes into '/proc' unless it stays on the filesystem of the root.
+        let tree = |args: &[&str]| -> String {
+            let args = [&["-T", "--grep", "^(proc|self)$"], args].concat();
+            String::from_utf8(nls(&args, Path::new("/")).stdout).unwrap()
+        };
+        assert!(tree(&[]).contains("self"));
+        assert!(!tree(&["--one-file-system"]).contains("self"));
+        assert!(tree(&["--one-file-system"]).contains("proc"));
+    }
+
+    #[test]
+    fn test_tree_dirs_only() {
+        let dir = fixture();
+        fs::create_dir_all(dir.path().join("sub").join("deep").join("deeper")).unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+
+        let tree = |args: &[&str]| -> Vec<String> {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            stdout
+                .lines()
+                .skip(1)
+                .map(|line| {
+                    line.trim_start_matches(['│', '├', '└', '─', ' '])
+                        .to_string()
+                })
+                .collect()
+        };
+
+        assert_eq!(tree(&["-T", "--dirs-only"]), ["sub", "deep", "deeper"]);
+        // It works with '--depth' and '-a'.
+        assert_eq!(tree(&["-T", "--dirs-only", "--depth=1"]), ["sub", "deep"]);
+        assert_eq!(
+            tree(&["-T", "--dirs-only", "-a"]),
+            [".git", "sub", "deep", "deeper"]
+        );
+    }
+
+    #[test]
+    fn test_tree_max_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..20 {
+            fs::write(dir.path().join(format!("f{:02}", i)), vec![0u8; i]).unwrap();
+        }
+
+        let stdout = String::from_utf8(nls(&["-T", "--max-entries=5"], dir.path()).stdout).unwrap();
+        let lines: Vec<&str> = stdout.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            [
+                "├── f00",
+                "├── f01",
+                "├── f02",
+                "├── f03",
+                "├── f04",
+                "└── ... (15 more)"
+            ]
+        );
+
+        // The limit is applied after the sort, so the largest ones are shown with '-S'.
+        let stdout =
+            String::from_utf8(nls(&["-T", "-S", "--max-entries=2"], dir.path()).stdout).unwrap();
+        let lines: Vec<&str> = stdout.lines().skip(1).collect();
+        assert_eq!(lines, ["├── f19", "├── f18", "└── ... (18 more)"]);
+
+        // Nothing is left out when there are not so many.
+        let stdout =
+            String::from_utf8(nls(&["-T", "--max-entries=20"], dir.path()).stdout).unwrap();
+        assert!(!stdout.contains("more)"));
+        assert!(stdout.ends_with("└── f19\n"));
+    }
+
+    #[test]
+    fn test_tree_reversed_at_every_level() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let minute = Duration::from_secs(60);
+        fs::create_dir_all(dir.path().join("x").join("y")).unwrap();
+        // The files are modified in the order of their names, and the directories after them.
+        let paths = ["a", "b", "x/c", "x/d", "x/y/e", "x/y/f", "x/y", "x"];
+        for (i, path) in paths.iter().enumerate() {
+            let path = dir.path().join(path);
+            if !path.exists() {
+                fs::write(&path, b"").unwrap();
+            }
+            let file = fs::File::open(&path).unwrap();
+            file.set_times(fs::FileTimes::new().set_modified(base + minute * i as u32))
+                .unwrap();
+        }
+
+        let tree = |args: &[&str]| -> Vec<String> {
+            let stdout = String::from_utf8(nls(args, dir.path()).stdout).unwrap();
+            stdout
+                .lines()
+                .skip(1)
+                .map(|line| {
+                    line.trim_start_matches(['│', '├', '└', '─', ' '])
+                        .to_string()
+                })
+                .collect()
+        };
+
+        assert_eq!(tree(&["-T"]), ["a", "b", "x", "c", "d", "y", "e", "f"]);
+        assert_eq!(
+            tree(&["-T", "-r"]),
+            ["x", "y", "f", "e", "d", "c", "b", "a"]
+        );
+        // The newest first with '-t', and the oldest first with '-r'.
+        assert_eq!(
+            tree(&["-T", "-t"]),
+            ["x", "y", "f", "e", "d", "c", "b", "a"]
+        );
+        assert_eq!(
+            tree(&["-T", "-t", "-r"]),
+            ["a", "b", "x", "c", "d", "y", "e", "f"]
+        );
+    }
+
+    // Run the nls binary and kill it if it doesn't finish in time, so a loop fails the test
+    // instead of hanging it.
+    fn nls_with_timeout(args: &[&str], path: &Path) -> String {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_nls"))
+            .args(args)
+            .arg(path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let start = SystemTime::now();
+        while child.try_wait().unwrap().is_none() {
+            if start.elapsed().unwrap() > Duration::from_secs(10) {
+                child.kill().unwrap();
+                panic!("nls {:?} didn't finish", args);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let mut stdout = String::new();
+        child.stdout.unwrap().read_to_string(&mut stdout).unwrap();
+        stdout
+    }
+
+    #[test]
+    fn test_symlink_loops_terminate() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        symlink(".", dir.path().join("self")).unwrap();
+        symlink("..", dir.path().join("sub").join("up")).unwrap();
+
+        // The links are shown, but never descended into.
+        let stdout = nls_with_timeout(&["-T", "--depth", "100"], dir.path());
+        assert_eq!(stdout.lines().count(), 4, "{}", stdout);
+        assert!(stdout.contains("── self\n"));
+        assert!(stdout.contains("── up\n"));
+
+        let stdout = nls_with_timeout(&["-R", "--depth", "100"], dir.path());
+        assert_eq!(headers(&stdout).len(), 2, "{}", stdout);
+
+        let stdout = nls_with_timeout(&["-T", "--json", "--depth", "100"], dir.path());
+        assert!(serde_json::from_str::<serde_json::Value>(&stdout).is_ok());
+    }
+
+    #[test]
+    fn test_dereference_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("real")).unwrap();
+        fs::write(dir.path().join("real").join("inner.txt"), b"abc").unwrap();
+        symlink("real", dir.path().join("link")).unwrap();
+        symlink("missing", dir.path().join("broken")).unwrap();
+
+        let stdout = String::from_utf8(nls(&["-l"], dir.path()).stdout).unwrap();
+        assert!(long_row(&stdout, "link -> real")[0].starts_with('l'));
+
+        // The link is shown as the directory it points to, and the broken link as itself.
+        let stdout = String::from_utf8(nls(&["-l", "-L"], dir.path()).stdout).unwrap();
+        assert!(long_row(&stdout, "link")[0].starts_with('d'));
+        assert!(!stdout.contains("link -> real"));
+        assert!(long_row(&stdout, "broken -> missing")[0].starts_with('l'));
+
+        let stdout = String::from_utf8(nls(&["-T"], dir.path()).stdout).unwrap();
+        assert_eq!(stdout.matches("inner.txt").count(), 1);
+        let stdout = String::from_utf8(nls(&["-T", "--dereference"], dir.path()).stdout).unwrap();
+        assert_eq!(stdout.matches("inner.txt").count(), 2);
+
+        let root = dir.path().display().to_string();
+        let stdout = String::from_utf8(nls(&["-R", "-L"], dir.path()).stdout).unwrap();
+        assert!(headers(&stdout).contains(&format!("{}/link:", root)));
+    }
+
+    #[test]
+    fn test_dereferenced_symlink_loops_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        symlink(".", dir.path().join("self")).unwrap();
+        symlink("..", dir.path().join("sub").join("up")).unwrap();
+
+        let stdout = nls_with_timeout(&["-T", "-L", "--depth", "100"], dir.path());
+        assert!(stdout.contains("── self [loop detected]\n"), "{}", stdout);
+        assert!(stdout.contains("── up [loop detected]\n"), "{}", stdout);
+
+        let root = dir.path().display().to_string();
+        let stdout = nls_with_timeout(&["-R", "-L", "--depth", "100"], dir.path());
+        assert!(
+            stdout.contains(&format!("{}/self: [loop detected]", root)),
+            "{}",
+            stdout
+        );
+        assert!(
+            stdout.contains(&format!("{}/sub/up: [loop detected]", root)),
+            "{}",
+            stdout
+        );
+
+        let stdout = nls_with_timeout(&["-T", "-L", "--json", "--depth", "100"], dir.path());
+        assert!(serde_json::from_str::<serde_json::Value>(&stdout).is_ok());
+    }
+
+    #[test]
+    fn test_json_tree_output() {
+        let dir = fixture();
+
+        let output = nls(&["--json", "-T"], dir.path());
+        let tree: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(tree["file_type"], "dir");
+
+        let children = tree["children"].as_array().unwrap();
+        let sub = children.iter().find(|c| c["name"] == "sub").unwrap();
+        let inner = &sub["children"][0];
+        assert_eq!(inner["name"], "inner.txt");
+        assert_eq!(inner["size"], 2048);
+        assert!(inner.get("children").is_none());
+    }
+
+    #[test]
+    fn test_long_listing_shows_symlink_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("plain"), b"").unwrap();
+        symlink("plain", dir.path().join("link")).unwrap();
+        symlink("missing", dir.path().join("dangling")).unwrap();
+
+        let stdout = String::from_utf8(nls(&["-l"], dir.path()).stdout).unwrap();
+        assert!(stdout.lines().any(|line| line.ends_with(" link -> plain")));
+        assert!(stdout
+            .lines()
+            .any(|line| line.ends_with(" dangling -> missing")));
+
+        // Only the target of the broken link is red.
+        let stdout = String::from_utf8(nls(&["-l", "--color=always"], dir.path()).stdout).unwrap();
+        assert!(stdout.contains(" -> \x1b[31mmissing\x1b[0m"));
+        assert!(stdout.contains(" -> plain"));
+    }
+
+    #[test]
+    fn test_symlink_arguments_listed_as_themselves() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("plain"), b"").unwrap();
+        symlink("plain", dir.path().join("link")).unwrap();
+        symlink("missing", dir.path().join("broken_link")).unwrap();
+
+        let output = nls(&[], &dir.path().join("broken_link"));
+        assert!(output.status.success());
+        assert!(output.stderr.is_empty());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "broken_link\n");
+
+        let stdout = nls(&["-l"], &dir.path().join("broken_link")).stdout;
+        let stdout = String::from_utf8(stdout).unwrap();
+        assert!(stdout.starts_with('l'));
+        assert!(stdout.ends_with(" broken_link -> missing\n"));
+
+        // The link to a file is not shown as its target either.
+        let stdout = nls(&["-l"], &dir.path().join("link")).stdout;
+        assert!(String::from_utf8(stdout)
+            .unwrap()
+            .ends_with(" link -> plain\n"));
+    }
+
+    #[test]
+    fn test_dereference_command_line() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("real")).unwrap();
+        fs::write(dir.path().join("real").join("inner.txt"), b"").unwrap();
+        fs::write(dir.path().join("plain"), b"hello").unwrap();
+        symlink("real", dir.path().join("dir_link")).unwrap();
+        symlink("plain", dir.path().join("file_link")).unwrap();
+        let dir_link = dir.path().join("dir_link");
+        let file_link = dir.path().join("file_link");
+
+        // The link to a directory is followed by default, but not with the details.
+        let stdout = String::from_utf8(nls(&[], &dir_link).stdout).unwrap();
+        assert_eq!(stdout, "inner.txt\n");
+        let stdout = String::from_utf8(nls(&["-l"], &dir_link).stdout).unwrap();
+        assert!(stdout.ends_with(" dir_link -> real\n"), "{}", stdout);
+
+        // The option follows it even with the details.
+        for option in ["--dereference-command-line", "-H"] {
+            let stdout = String::from_utf8(nls(&["-l", option], &dir_link).stdout).unwrap();
+            assert!(stdout.starts_with("total "));
+            assert!(stdout.ends_with(" inner.txt\n"));
+        }
+
+        // The link to a file keeps its name, but shows the metadata of its target.
+        let stdout = nls(&["-l", "--dereference-command-line"], &file_link).stdout;
+        let stdout = String::from_utf8(stdout).unwrap();
+        assert!(stdout.starts_with('-'));
+        assert!(stdout.contains(" 5 "));
+        assert!(stdout.ends_with(" file_link\n"));
+
+        // The links inside the directories are still shown as themselves.
+        let stdout = nls(&["-l", "--dereference-command-line"], dir.path()).stdout;
+        let stdout = String::from_utf8(stdout).unwrap();
+        assert!(stdout.contains(" file_link -> plain\n"));
+    }
+
+    #[test]
+    fn test_long_listing_starts_with_total() {
+        let dir = fixture();
+        fs::write(dir.path().join("big"), vec![1u8; 100_000]).unwrap();
+
+        // Sum the 512-byte blocks of the visible entries, and count them in 1024-byte blocks.
+        let blocks: u64 = ["visible.txt", "sub", "big"]
+            .iter()
+            .map(|name| {
+                fs::symlink_metadata(dir.path().join(name))
+                    .unwrap()
+                    .blocks()
+            })
+            .sum();
+
+        let stdout = String::from_utf8(nls(&["-l"], dir.path()).stdout).unwrap();
+        assert_eq!(
+            stdout.lines().next().unwrap(),
+            format!("total {}", blocks.div_ceil(2))
+        );
+
+        // A single file has no total.
+        let stdout = String::from_utf8(nls(&["-l"], &dir.path().join("big")).stdout).unwrap();
+        assert!(!stdout.starts_with("total"));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_device_numbers() {
+        // '/dev/null' is always the char device 1, 3 on Linux.
+        let output = nls(&["-l"], Path::new("/dev/null"));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.starts_with('c'));
+        assert!(stdout.contains(" 1, 3 "), "{}", stdout);
+    }
+
+    #[test]
+    fn test_inode_numbers() {
+        let dir = fixture();
+        let inode = fs::metadata(dir.path().join("visible.txt")).unwrap().ino();
+
+        let stdout = String::from_utf8(nls(&["-i"], dir.path()).stdout).unwrap();
+        assert!(stdout.contains(&format!("{} visible.txt\n", inode)));
+
+        // The inode is the first column in long mode.
+        let stdout = String::from_utf8(nls(&["-l", "-i"], dir.path()).stdout).unwrap();
+        let row = long_row(&stdout, "visible.txt");
+        assert_eq!(row[0], inode.to_string());
+        assert!(row[1].starts_with('-'));
+
+        let stdout = String::from_utf8(nls(&["-T", "-i"], dir.path()).stdout).unwrap();
+        assert!(stdout.contains(&format!("── {} visible.txt", inode)));
+    }
+
+    #[test]
+    fn test_directory_lists_itself() {
+        let dir = fixture();
+        let sub = dir.path().join("sub");
+
+        let stdout = String::from_utf8(nls(&["-d"], &sub).stdout).unwrap();
+        assert_eq!(stdout, "sub\n");
+
+        let stdout = String::from_utf8(nls(&["-l", "-d"], &sub).stdout).unwrap();
+        assert_eq!(stdout.lines().count(), 1);
+        assert!(long_row(&stdout, "sub")[0].starts_with('d'));
+
+        let stdout = String::from_utf8(nls(&["-d", "-R"], &sub).stdout).unwrap();
+        assert_eq!(stdout, "sub\n");
+
+        let stdout = String::from_utf8(nls(&["-d"], Path::new("/")).stdout).unwrap();
+        assert_eq!(stdout, "/\n");
+    }
+}